@@ -0,0 +1,264 @@
+// Inspection and extraction of backup objects, which is what ARCHIVE
+// sends to `kget`. A backup object (DOBAK, prolog 0x2b62) looks like
+// this:
+/*
+ * prolog (5 nibbles)
+ * size of everything after the prolog (5 nibbles)
+ * name of the backup, usually HOMEDIR (ASCIX)
+ * the backed-up object, a directory for ARCHIVE
+ * any other objects the calculator saved along with it
+ */
+// Each variable in a directory can be pulled out and written as a
+// standalone object, which the calculator will accept just like a
+// file sent from it directly.
+
+use std::path::PathBuf;
+
+use console::style;
+
+use crate::hp_object::{self, Directory};
+
+// Where the contents of a backup object are in its nibbles.
+struct Backup {
+    name: String,
+    // start and length of the backed-up object
+    start: usize,
+    len: usize,
+    // start and length of each object after it
+    others: Vec<(usize, usize)>,
+}
+
+fn read_backup(nibs: &[u8]) -> Result<Backup, String> {
+    let total_len = hp_object::calc_object_size(nibs)? as usize;
+    if total_len > nibs.len() {
+	return Err("backup length is greater than file size; file may be corrupt".to_owned());
+    }
+    // Names in backups are usually ASCIX like in directories, but
+    // accept a plain ASCIC name too.
+    let char_len = hp_object::read_nibbles(nibs, 10, 2)? as usize;
+    let name = hp_object::read_hp_string(nibs, 12, char_len)?;
+    let mut start = 12 + char_len * 2;
+    if hp_object::read_nibbles(nibs, start, 2)? as usize == char_len {
+	start += 2;
+    }
+    if start + 5 > total_len {
+	return Err("backup name runs past the end of the backup; file may be corrupt".to_owned());
+    }
+
+    // A directory's size can't be found by walking to the end of the
+    // data here, because other objects may follow it.
+    let len = match hp_object::get_prolog(&nibs[start..total_len])? {
+	0x2a96 => hp_object::read_directory(&nibs[start..total_len])?.len,
+	_ => hp_object::calc_object_size(&nibs[start..total_len])? as usize,
+    };
+    if start + len > total_len {
+	return Err("backed-up object runs past the end of the backup; file may be corrupt".to_owned());
+    }
+    let mut others: Vec<(usize, usize)> = Vec::new();
+    let mut index = start + len;
+    // anything shorter than a prolog is padding
+    while index + 5 <= total_len {
+	let other_len = hp_object::calc_object_size(&nibs[index..total_len])? as usize;
+	if other_len == 0 || index + other_len > total_len {
+	    return Err("object after the backup runs past its end; file may be corrupt".to_owned());
+	}
+	others.push((index, other_len));
+	index += other_len;
+    }
+
+    return Ok(Backup { name, start, len, others });
+}
+
+// Get the directory from the archive in `nibs`, which is either a
+// backup object or a bare directory. Returns the directory and the
+// offset of its prolog in `nibs`.
+fn find_directory(nibs: &[u8]) -> Result<(Directory, usize), String> {
+    match hp_object::get_prolog(nibs)? {
+	0x2b62 => {
+	    let backup = read_backup(nibs)?;
+	    let dir = hp_object::read_directory(&nibs[backup.start..backup.start + backup.len])?;
+	    Ok((dir, backup.start))
+	},
+	0x2a96 => Ok((hp_object::read_directory(nibs)?, 0)),
+	_ => Err("file is not a backup object or directory".to_owned()),
+    }
+}
+
+fn type_name(nibs: &[u8]) -> String {
+    match hp_object::get_prolog(nibs) {
+	Ok(prolog) => match hp_object::prolog_name(prolog) {
+	    Some(name) => name.to_owned(),
+	    None => format!("{:#07x}", prolog),
+	},
+	Err(_) => "?".to_owned(),
+    }
+}
+
+// Print a directory's variables, recursing into subdirectories.
+// `dir_nibs` starts at the directory's prolog.
+fn print_directory(dir: &Directory, dir_nibs: &[u8], indent: usize) -> Result<(), String> {
+    for entry in &dir.entries {
+	let obj = &dir_nibs[entry.start..entry.start + entry.len];
+	println!("{:indent$}{:<16} {:<9} {:>9} {}",
+		 "",
+		 style(&entry.name).yellow().bright(),
+		 type_name(obj),
+		 // same units as BYTES
+		 format!("{:.1}", entry.len as f32 / 2.0),
+		 style(hp_object::format_crc(hp_object::crc_nibbles(obj))).blue().bright(),
+		 indent = indent);
+	if hp_object::get_prolog(obj)? == 0x2a96 {
+	    print_directory(&hp_object::read_directory(obj)?, obj, indent + 2)?;
+	}
+    }
+    return Ok(());
+}
+
+// Collect the names of the libraries stored as variables in `dir`
+// and its subdirectories.
+fn find_libraries(dir: &Directory, dir_nibs: &[u8], prefix: &str, libs: &mut Vec<String>) -> Result<(), String> {
+    for entry in &dir.entries {
+	let obj = &dir_nibs[entry.start..entry.start + entry.len];
+	match hp_object::get_prolog(obj)? {
+	    0x2b40 => libs.push(format!("{}{}", prefix, entry.name)),
+	    0x2a96 => find_libraries(&hp_object::read_directory(obj)?, obj,
+				     &format!("{}{}/", prefix, entry.name), libs)?,
+	    _ => {},
+	}
+    }
+    return Ok(());
+}
+
+fn list_archive(nibs: &[u8]) -> Result<(), String> {
+    let (home, home_start) = find_directory(nibs)?;
+    let home_nibs = &nibs[home_start..home_start + home.len];
+
+    if hp_object::get_prolog(nibs)? == 0x2b62 {
+	let backup = read_backup(nibs)?;
+	println!("Backup {}", style(&backup.name).yellow().bright());
+	for (start, len) in &backup.others {
+	    let obj = &nibs[*start..*start + *len];
+	    // ARCHIVE saves the flags as binary integers after HOME
+	    if hp_object::get_prolog(obj)? == 0x2a4e {
+		let mut value = String::new();
+		// the body is stored least significant nibble first
+		for n in obj[10..].iter().rev() {
+		    value.push_str(&format!("{:X}", n));
+		}
+		println!("Flags: {}", style(format!("#{}h", value)).blue().bright());
+	    } else {
+		println!("Other object: {}, {:.1} bytes", type_name(obj), *len as f32 / 2.0);
+	    }
+	}
+    }
+
+    println!("Attached library: {}", match home.attached_lib {
+	0x7ff => "none".to_owned(),
+	n => format!("{}", n),
+    });
+
+    let mut libs: Vec<String> = Vec::new();
+    find_libraries(&home, home_nibs, "", &mut libs)?;
+    if !libs.is_empty() {
+	println!("Libraries: {}", libs.join(", "));
+    }
+
+    println!("Variables:");
+    print_directory(&home, home_nibs, 2)?;
+    return Ok(());
+}
+
+// Find the variable at `var_path` (names separated by '/') and return
+// its nibbles.
+fn find_variable<'a>(nibs: &'a [u8], var_path: &str) -> Result<&'a [u8], String> {
+    let (mut dir, home_start) = find_directory(nibs)?;
+    let mut dir_nibs = &nibs[home_start..home_start + dir.len];
+
+    let names: Vec<&str> = var_path.split('/').filter(|n| !n.is_empty()).collect();
+    for (pos, name) in names.iter().enumerate() {
+	let entry = match dir.entries.iter().find(|e| e.name == *name) {
+	    Some(e) => e,
+	    None => return Err(format!("no variable named {:?} in archive", var_path)),
+	};
+	let obj = &dir_nibs[entry.start..entry.start + entry.len];
+	if pos == names.len() - 1 {
+	    return Ok(obj);
+	}
+	dir = match hp_object::read_directory(obj) {
+	    Ok(d) => d,
+	    Err(_) => return Err(format!("{:?} is not a directory", name)),
+	};
+	dir_nibs = obj;
+    }
+    return Err("no variable name given".to_owned());
+}
+
+pub fn list(path: &PathBuf) {
+    let (_, nibs) = match hp_object::read_object_file(path) {
+	Ok(f) => f,
+	Err(e) => return crate::helpers::error_handler(format!("Error: {}", e)),
+    };
+    if let Err(e) = list_archive(&nibs) {
+	crate::helpers::error_handler(format!("Error: {}", e));
+    }
+}
+
+// Write the variable `var_path` from the archive at `path` to
+// `output`, or to a file named after the variable if `output` is
+// None.
+pub fn extract(path: &PathBuf, var_path: &str, output: &Option<PathBuf>, overwrite: &bool) -> PathBuf {
+    let (romrev, nibs) = match hp_object::read_object_file(path) {
+	Ok(f) => f,
+	Err(e) => { crate::helpers::error_handler(format!("Error: {}", e)); (' ', Vec::new()) },
+    };
+
+    let obj = match find_variable(&nibs, var_path) {
+	Ok(o) => o,
+	Err(e) => { crate::helpers::error_handler(format!("Error: {}", e)); &[] },
+    };
+
+    let out_path = match output {
+	Some(p) => p.to_path_buf(),
+	None => PathBuf::from(var_path.rsplit('/').next().unwrap()),
+    };
+    let final_path = match overwrite {
+	true => out_path,
+	false => crate::helpers::get_unique_path(out_path),
+    };
+
+    if let Err(e) = hp_object::write_object_file(&final_path, romrev, obj) {
+	crate::helpers::error_handler(format!("Error: {}", e));
+    }
+    println!("Extracted {} to {}",
+	     style(var_path).yellow().bright(),
+	     style(final_path.display()).yellow().bright());
+    return final_path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hp_object::nibbles;
+
+    // backup of the real 1 named A
+    const BACKUP: &str = "26B20 02000 10 14 10 33920 000 000000000010 0";
+
+    #[test]
+    fn reads_name_and_object() {
+	let backup = read_backup(&nibbles(BACKUP)).unwrap();
+	assert_eq!(backup.name, "A");
+	assert_eq!((backup.start, backup.len), (16, 21));
+	assert!(backup.others.is_empty());
+    }
+
+    #[test]
+    fn corrupt_name_length_is_an_error() {
+	let mut nibs = nibbles(BACKUP);
+	// name length FF, with enough of something else after the backup
+	// that the name can be read
+	nibs[10] = 0xf;
+	nibs[11] = 0xf;
+	nibs.extend([0; 600]);
+	assert!(read_backup(&nibs).is_err());
+    }
+}
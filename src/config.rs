@@ -0,0 +1,187 @@
+// The config file, with named profiles for each calculator and
+// adapter, so you don't have to type the port and speed every time.
+// It is config.toml in the alum folder of the config directory
+// (~/.config/alum/config.toml on Linux), or the file in $ALUM_CONFIG:
+/*
+ * # profile to use without --profile
+ * default = "48gx"
+ *
+ * [profiles.48gx]
+ * port = "/dev/ttyUSB0"
+ * baud = 9600
+ *
+ * [profiles.50g]
+ * port = "/dev/ttyUSB1"
+ * baud = 115200
+ * protocol = "kermit"
+ * parity = "odd"
+ * finish = true
+ */
+// Everything in a profile is optional, and flags and options on the
+// command line win over the profile.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Xmodem,
+    Kermit,
+}
+
+#[derive(Deserialize, clap::ValueEnum, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Deserialize, clap::ValueEnum, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowControl {
+    None,
+    // XON/XOFF
+    Software,
+    // RTS/CTS
+    Hardware,
+}
+
+// How characters go over the wire, besides the speed. The default is
+// 8N1 with no flow control, which is what the HP 48 uses unless IOPAR
+// is changed.
+#[derive(Clone, Copy, Debug)]
+pub struct LineSettings {
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: u8,
+    pub flow_control: FlowControl,
+}
+
+impl Default for LineSettings {
+    fn default() -> Self {
+	LineSettings { data_bits: 8, parity: Parity::None, stop_bits: 1, flow_control: FlowControl::None }
+    }
+}
+
+impl LineSettings {
+    // Apply these settings to a port that is about to be opened.
+    pub fn apply(&self, builder: serialport::SerialPortBuilder) -> serialport::SerialPortBuilder {
+	let data_bits = match self.data_bits {
+	    5 => serialport::DataBits::Five,
+	    6 => serialport::DataBits::Six,
+	    7 => serialport::DataBits::Seven,
+	    _ => serialport::DataBits::Eight,
+	};
+	let parity = match self.parity {
+	    Parity::None => serialport::Parity::None,
+	    Parity::Odd => serialport::Parity::Odd,
+	    Parity::Even => serialport::Parity::Even,
+	};
+	let stop_bits = match self.stop_bits {
+	    2 => serialport::StopBits::Two,
+	    _ => serialport::StopBits::One,
+	};
+	let flow_control = match self.flow_control {
+	    FlowControl::None => serialport::FlowControl::None,
+	    FlowControl::Software => serialport::FlowControl::Software,
+	    FlowControl::Hardware => serialport::FlowControl::Hardware,
+	};
+	return builder.data_bits(data_bits).parity(parity).stop_bits(stop_bits).flow_control(flow_control);
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub port: Option<PathBuf>,
+    pub baud: Option<u32>,
+    pub data_bits: Option<u8>,
+    pub parity: Option<Parity>,
+    pub stop_bits: Option<u8>,
+    pub flow_control: Option<FlowControl>,
+    // for commands that talk to either server (shell, sync)
+    pub protocol: Option<Protocol>,
+    // how long to wait for each packet from the calculator, in ms
+    pub timeout: Option<u64>,
+    // extra wait in ms before each packet, for slow calculators or
+    // adapters
+    pub packet_delay: Option<u64>,
+    // default flags
+    pub finish: Option<bool>,
+    pub overwrite: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    default: Option<String>,
+    #[serde(default)]
+    profiles: HashMap<String, Profile>,
+}
+
+// the profile in use, so the serial code can get at the timeouts and
+// delays without passing them through every function
+static PROFILE: OnceLock<Profile> = OnceLock::new();
+
+fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("ALUM_CONFIG") {
+	return Some(PathBuf::from(path));
+    }
+    return dirs::config_dir().map(|d| d.join("alum").join("config.toml"));
+}
+
+fn read_config() -> Result<Config, String> {
+    let path = match config_path() {
+	Some(p) if p.exists() => p,
+	_ => return Ok(Config::default()),
+    };
+    let text = match std::fs::read_to_string(&path) {
+	Ok(t) => t,
+	Err(e) => return Err(format!("couldn't read {}: {}", path.display(), e)),
+    };
+    return match toml::from_str(&text) {
+	Ok(c) => Ok(c),
+	Err(e) => Err(format!("bad config file {}: {}", path.display(), e)),
+    };
+}
+
+// Load the profile named `name`, or the default profile, and make it
+// the one in use. Without a config file, everything is unset.
+pub fn load(name: &Option<String>) -> Result<Profile, String> {
+    let mut config = read_config()?;
+    let profile = match name.as_ref().or(config.default.as_ref()) {
+	Some(n) => match config.profiles.remove(n) {
+	    Some(p) => p,
+	    None => return Err(format!("no profile named {:?} in {}", n,
+				       config_path().map(|p| p.display().to_string()).unwrap_or_default())),
+	},
+	None => Profile::default(),
+    };
+    // the command line checks these with clap, but here we have to do it
+    if profile.data_bits.is_some_and(|b| !(5..=8).contains(&b)) {
+	return Err("data_bits in profile must be 5 to 8".to_owned());
+    }
+    if profile.stop_bits.is_some_and(|b| !(1..=2).contains(&b)) {
+	return Err("stop_bits in profile must be 1 or 2".to_owned());
+    }
+    let _ = PROFILE.set(profile.clone());
+    return Ok(profile);
+}
+
+fn profile() -> Profile {
+    return PROFILE.get().cloned().unwrap_or_default();
+}
+
+pub fn timeout() -> Duration {
+    return Duration::from_millis(profile().timeout.unwrap_or(3500));
+}
+
+pub fn packet_delay() -> Duration {
+    return Duration::from_millis(profile().packet_delay.unwrap_or(0));
+}
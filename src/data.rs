@@ -0,0 +1,379 @@
+// Conversion of arrays and lists to and from CSV and JSON, so that
+// data collected on the calculator (like ΣDAT) can go to a
+// spreadsheet and back. An array (DOARRY, prolog 0x29e8) looks like
+// this:
+/*
+ * prolog (5 nibbles)
+ * size of everything after the prolog (5 nibbles)
+ * prolog of the element type, like DOREAL (5 nibbles)
+ * number of dimensions (5 nibbles)
+ * each dimension (5 nibbles each)
+ * elements, row by row, without their prologs
+ */
+// A list (DOLIST) is a composite object like a program, so its
+// elements are full objects and can be anything, including other
+// lists.
+
+use std::path::PathBuf;
+
+use console::style;
+
+use crate::hp_object;
+use crate::hp_real::HpNumber;
+
+pub enum Value {
+    Number(HpNumber),
+    Str(String),
+    List(Vec<Value>),
+    // dimensions, then elements row by row
+    Array(Vec<usize>, Vec<Value>),
+}
+
+// What `import` should make from a CSV file.
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum ImportKind {
+    // one row of the CSV per row of the matrix
+    Matrix,
+    // all values in order
+    Vector,
+    // one list per row, inside a list
+    List,
+}
+
+pub fn read_string(nibs: &[u8]) -> Result<String, String> {
+    // the size field includes itself
+    let size = hp_object::read_nibbles(nibs, 5, 5)? as usize;
+    if size < 5 {
+	return Err("string has a bad size field".to_owned());
+    }
+    return hp_object::read_hp_string(nibs, 10, (size - 5) / 2);
+}
+
+fn read_array(nibs: &[u8]) -> Result<Value, String> {
+    let elem_prolog = hp_object::read_nibbles(nibs, 10, 5)?;
+    let dim_count = hp_object::read_nibbles(nibs, 15, 5)? as usize;
+    let mut dims: Vec<usize> = Vec::new();
+    for i in 0..dim_count {
+	dims.push(hp_object::read_nibbles(nibs, 20 + i * 5, 5)? as usize);
+    }
+    // the dimensions come from the file, so a corrupt one could be
+    // anything
+    let count = match dims.iter().try_fold(1usize, |acc, d| acc.checked_mul(*d)) {
+	Some(c) => c,
+	None => return Err("array dimensions are too big".to_owned()),
+    };
+    let mut index = 20 + dim_count * 5;
+
+    let mut values: Vec<Value> = Vec::new();
+    for _ in 0..count {
+	if let Some(len) = HpNumber::body_len(elem_prolog) {
+	    if index + len > nibs.len() {
+		return Err("array is shorter than its dimensions say".to_owned());
+	    }
+	    values.push(Value::Number(HpNumber::from_body(&nibs[index..], elem_prolog)?));
+	    index += len;
+	} else if elem_prolog == 0x2a2c {
+	    // strings keep their size field, like in a message table
+	    let size = hp_object::read_nibbles(nibs, index, 5)? as usize;
+	    if size < 5 {
+		return Err("array string has a bad size field".to_owned());
+	    }
+	    values.push(Value::Str(hp_object::read_hp_string(nibs, index + 5, (size - 5) / 2)?));
+	    index += size;
+	} else {
+	    return Err(format!("can't convert arrays of {}",
+			       hp_object::prolog_name(elem_prolog).unwrap_or("unknown objects")));
+	}
+    }
+    return Ok(Value::Array(dims, values));
+}
+
+// Read the array, list, number, or string at the start of `nibs`.
+pub fn read_value(nibs: &[u8]) -> Result<Value, String> {
+    let prolog = hp_object::get_prolog(nibs)?;
+    if HpNumber::body_len(prolog).is_some() {
+	return Ok(Value::Number(HpNumber::from_body(&nibs[5..], prolog)?));
+    }
+    match prolog {
+	0x2a2c => Ok(Value::Str(read_string(nibs)?)),
+	0x29e8 => read_array(nibs),
+	0x2a74 => {
+	    let mut values: Vec<Value> = Vec::new();
+	    for (start, _) in hp_object::composite_elements(nibs)? {
+		values.push(read_value(&nibs[start..])?);
+	    }
+	    Ok(Value::List(values))
+	},
+	_ => Err(format!("can't convert {} objects",
+			 hp_object::prolog_name(prolog).unwrap_or("unknown"))),
+    }
+}
+
+fn push_field(nibs: &mut Vec<u8>, value: usize, count: usize) {
+    for i in 0..count {
+	nibs.push(((value >> (i * 4)) & 0xf) as u8);
+    }
+}
+
+// Make the object for `value`, including its prolog.
+pub fn value_to_nibbles(value: &Value) -> Result<Vec<u8>, String> {
+    let mut nibs: Vec<u8> = Vec::new();
+    match value {
+	Value::Number(n) => nibs = n.to_nibbles(),
+	Value::Str(s) => {
+	    push_field(&mut nibs, 0x2a2c, 5);
+	    push_field(&mut nibs, 5 + s.chars().count() * 2, 5);
+	    for c in s.chars() {
+		push_field(&mut nibs, crate::helpers::char_to_hp_char(c) as usize, 2);
+	    }
+	},
+	Value::List(values) => {
+	    push_field(&mut nibs, 0x2a74, 5);
+	    for v in values {
+		nibs.extend(value_to_nibbles(v)?);
+	    }
+	    push_field(&mut nibs, 0x312b, 5);
+	},
+	Value::Array(dims, values) => {
+	    // every element has to be the same type of number
+	    let elem_prolog = match values.first() {
+		Some(Value::Number(n)) => n.prolog(),
+		_ => return Err("arrays can only hold real or complex numbers".to_owned()),
+	    };
+	    let mut body: Vec<u8> = Vec::new();
+	    for v in values {
+		match v {
+		    Value::Number(n) if n.prolog() == elem_prolog => body.extend(n.body_nibbles()),
+		    _ => return Err("arrays can't mix real and complex numbers".to_owned()),
+		}
+	    }
+	    push_field(&mut nibs, 0x29e8, 5);
+	    push_field(&mut nibs, 5 + 5 + 5 + dims.len() * 5 + body.len(), 5);
+	    push_field(&mut nibs, elem_prolog as usize, 5);
+	    push_field(&mut nibs, dims.len(), 5);
+	    for d in dims {
+		push_field(&mut nibs, *d, 5);
+	    }
+	    nibs.extend(body);
+	},
+    }
+    return Ok(nibs);
+}
+
+// Complex numbers are {"re": 1, "im": 2}, and arrays are nested JSON
+// arrays, one level per dimension.
+fn to_json(value: &Value) -> String {
+    match value {
+	Value::Number(HpNumber::Real(r)) => r.to_decimal_string(),
+	Value::Number(HpNumber::Complex(re, im)) =>
+	    format!("{{\"re\": {}, \"im\": {}}}", re.to_decimal_string(), im.to_decimal_string()),
+	Value::Str(s) => crate::helpers::json_string(s),
+	Value::List(values) => {
+	    let items: Vec<String> = values.iter().map(to_json).collect();
+	    format!("[{}]", items.join(", "))
+	},
+	Value::Array(dims, values) => {
+	    let items: Vec<String> = values.iter().map(to_json).collect();
+	    match dims.len() {
+		2 => {
+		    let rows: Vec<String> = items.chunks(dims[1].max(1))
+			.map(|row| format!("[{}]", row.join(", ")))
+			.collect();
+		    format!("[{}]", rows.join(",\n "))
+		},
+		_ => format!("[{}]", items.join(", ")),
+	    }
+	},
+    }
+}
+
+// Strings are always quoted, so a spreadsheet keeps them as text.
+fn value_to_csv_field(value: &Value) -> Result<String, String> {
+    match value {
+	Value::Number(HpNumber::Real(r)) => Ok(r.to_decimal_string()),
+	// has a comma, so it needs quotes
+	Value::Number(HpNumber::Complex(re, im)) =>
+	    Ok(format!("\"({},{})\"", re.to_decimal_string(), im.to_decimal_string())),
+	Value::Str(s) => Ok(format!("\"{}\"", s.replace('"', "\"\""))),
+	_ => Err("CSV can only hold lists nested two deep".to_owned()),
+    }
+}
+
+fn to_csv(value: &Value) -> Result<String, String> {
+    let rows: Vec<Vec<&Value>> = match value {
+	Value::Array(dims, values) if dims.len() == 2 =>
+	    values.chunks(dims[1].max(1)).map(|row| row.iter().collect()).collect(),
+	Value::Array(_, values) => vec![values.iter().collect()],
+	Value::List(values) => {
+	    if values.iter().all(|v| matches!(v, Value::List(_))) {
+		values.iter().map(|v| match v {
+		    Value::List(row) => row.iter().collect(),
+		    _ => Vec::new(),
+		}).collect()
+	    } else {
+		vec![values.iter().collect()]
+	    }
+	},
+	_ => vec![vec![value]],
+    };
+
+    let mut out = String::new();
+    for row in rows {
+	let fields: Result<Vec<String>, String> = row.into_iter().map(value_to_csv_field).collect();
+	out.push_str(&fields?.join(","));
+	out.push('\n');
+    }
+    return Ok(out);
+}
+
+// Split CSV text into rows of fields. Quoted fields can contain
+// commas, line breaks, and doubled quotes. Blank lines are skipped.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut fields: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+    loop {
+	let c = chars.next();
+	// a line break in quotes is part of the field
+	let end_of_row = match c {
+	    None => true,
+	    Some('\n') => !in_quotes,
+	    _ => false,
+	};
+	if end_of_row {
+	    fields.push(std::mem::take(&mut field));
+	    if fields.len() > 1 || !fields[0].trim().is_empty() {
+		rows.push(std::mem::take(&mut fields));
+	    }
+	    fields.clear();
+	    if c.is_none() {
+		break;
+	    }
+	    continue;
+	}
+	match c.unwrap() {
+	    '"' if in_quotes && chars.peek() == Some(&'"') => {
+		field.push('"');
+		chars.next();
+	    },
+	    '"' => in_quotes = !in_quotes,
+	    ',' if !in_quotes => fields.push(std::mem::take(&mut field)),
+	    '\r' if !in_quotes && chars.peek() == Some(&'\n') => {},
+	    c => field.push(c),
+	}
+    }
+    return rows;
+}
+
+fn parse_csv_value(field: &str, extended: bool) -> Value {
+    match HpNumber::parse(field, extended) {
+	Ok(n) => Value::Number(n),
+	Err(_) => Value::Str(field.to_owned()),
+    }
+}
+
+fn csv_to_value(text: &str, kind: &ImportKind, extended: bool) -> Result<Value, String> {
+    let rows = parse_csv(text);
+    if rows.is_empty() {
+	return Err("CSV file has no data".to_owned());
+    }
+    match kind {
+	ImportKind::List => Ok(Value::List(rows.iter().map(|row| {
+	    Value::List(row.iter().map(|f| parse_csv_value(f, extended)).collect())
+	}).collect())),
+	ImportKind::Vector | ImportKind::Matrix => {
+	    let width = rows[0].len();
+	    let mut values: Vec<Value> = Vec::new();
+	    for (pos, row) in rows.iter().enumerate() {
+		if row.len() != width && matches!(kind, ImportKind::Matrix) {
+		    return Err(format!("row {} has {} values, but row 1 has {}", pos + 1, row.len(), width));
+		}
+		for f in row {
+		    match HpNumber::parse(f, extended) {
+			Ok(n) => values.push(Value::Number(n)),
+			Err(e) => return Err(format!("row {}: {}", pos + 1, e)),
+		    }
+		}
+	    }
+	    let dims = match kind {
+		ImportKind::Vector => vec![values.len()],
+		_ => vec![rows.len(), width],
+	    };
+	    Ok(Value::Array(dims, values))
+	},
+    }
+}
+
+// Write the array or list at `path` to `output` as CSV or JSON,
+// depending on the extension of `output`.
+pub fn export(path: &PathBuf, output: &PathBuf, overwrite: &bool) {
+    let final_path = match overwrite {
+	true => output.to_path_buf(),
+	false => crate::helpers::get_unique_path(output.to_path_buf()),
+    };
+    let ext = output.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    let result = hp_object::read_object_file(path)
+	.and_then(|(_, nibs)| read_value(&nibs))
+	.and_then(|value| match ext.as_deref() {
+	    Some("json") => Ok(to_json(&value) + "\n"),
+	    Some("csv") => to_csv(&value),
+	    _ => Err(format!("{} must end in .csv or .json", output.display())),
+	})
+	.and_then(|text| match std::fs::write(&final_path, text) {
+	    Ok(_) => Ok(()),
+	    Err(e) => Err(format!("couldn't write {}: {}", final_path.display(), e)),
+	});
+    match result {
+	Ok(_) => println!("Wrote {}", style(final_path.display()).yellow().bright()),
+	Err(e) => crate::helpers::error_handler(format!("Error: {}", e)),
+    }
+}
+
+// Make an array or list object at `output` from the CSV file `path`.
+pub fn import(path: &PathBuf, output: &PathBuf, kind: &ImportKind, extended: &bool, overwrite: &bool) -> PathBuf {
+    let final_path = match overwrite {
+	true => output.to_path_buf(),
+	false => crate::helpers::get_unique_path(output.to_path_buf()),
+    };
+    let result = match std::fs::read_to_string(path) {
+	Ok(text) => csv_to_value(&text, kind, *extended),
+	Err(e) => Err(format!("couldn't read {}: {}", path.display(), e)),
+    }
+	.and_then(|value| value_to_nibbles(&value))
+	.and_then(|nibs| hp_object::write_object_file(&final_path, hp_object::DEFAULT_ROMREV, &nibs));
+    match result {
+	Ok(_) => println!("Wrote {}", style(final_path.display()).yellow().bright()),
+	Err(e) => crate::helpers::error_handler(format!("Error: {}", e)),
+    }
+    return final_path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_round_trips_commas_quotes_and_line_breaks() {
+	let texts = ["a,b", "two\nlines", "say \"hi\"", "crlf\r\nend"];
+	let row: Vec<Value> = texts.iter().map(|t| Value::Str(t.to_string())).collect();
+	let csv = to_csv(&Value::List(vec![Value::List(row)])).unwrap();
+	assert_eq!(parse_csv(&csv), vec![texts.map(|t| t.to_owned()).to_vec()]);
+    }
+
+    #[test]
+    fn csv_skips_blank_lines() {
+	assert_eq!(parse_csv("1,2\r\n\r\n3,4"), vec![vec!["1", "2"], vec!["3", "4"]]);
+    }
+
+    #[test]
+    fn huge_array_dimensions_are_an_error() {
+	// array of reals with four dimensions of FFFFF
+	let mut nibs: Vec<u8> = Vec::new();
+	for field in [0x29e8, 0x30, 0x2933, 4, 0xfffff, 0xfffff, 0xfffff, 0xfffff] {
+	    push_field(&mut nibs, field, 5);
+	}
+	assert_eq!(read_value(&nibs).err(), Some("array dimensions are too big".to_owned()));
+    }
+}
@@ -0,0 +1,88 @@
+// Send one file to many calculators at once, like loading the same
+// libraries onto a classroom set on a USB hub. Each port gets its own
+// thread and progress bar, and a table at the end shows which
+// calculators got the file.
+
+// The transfers return their errors, which go in the table at the
+// end. Anything else that calls helpers::error_handler(), which
+// exits, panics with the message instead in these threads (see
+// helpers::set_worker()), so one bad calculator doesn't stop the
+// others and we get the message when we join the thread.
+
+use std::panic::PanicHookInfo;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use console::style;
+use indicatif::MultiProgress;
+
+use crate::helpers::WorkerError;
+
+// `ports` has the name of each port and the port, or why it couldn't
+// be opened. `send` does the transfer on one port.
+pub fn deploy<F>(path: &PathBuf, ports: Vec<(String, Result<Box<dyn serialport::SerialPort>, String>)>, send: F)
+where F: Fn(&mut Box<dyn serialport::SerialPort>) -> Result<(), String> + Sync {
+    println!("Sending {:?} to {} calculators...",
+	     style(path.file_name().unwrap()).yellow().bright(), ports.len());
+
+    let bars = MultiProgress::new();
+    // error_handler() panics in the threads, and the message goes in
+    // the table, so don't print it here as well. Any other panic is a
+    // bug, and is printed as usual.
+    let default_hook: Arc<dyn Fn(&PanicHookInfo) + Send + Sync> = Arc::from(std::panic::take_hook());
+    let hook = default_hook.clone();
+    std::panic::set_hook(Box::new(move |info| {
+	if !info.payload().is::<WorkerError>() {
+	    hook(info);
+	}
+    }));
+
+    let results: Vec<(String, Result<Duration, String>)> = std::thread::scope(|scope| {
+	let mut threads = Vec::new();
+	for (name, port) in ports {
+	    let bars = bars.clone();
+	    let send = &send;
+	    let label = name.clone();
+	    threads.push((name, scope.spawn(move || {
+		let mut port = port?;
+		crate::helpers::set_worker(bars, label);
+		let start = Instant::now();
+		send(&mut port)?;
+		return Ok(start.elapsed());
+	    })));
+	}
+	threads.into_iter().map(|(name, thread)| {
+	    let result = match thread.join() {
+		Ok(r) => r,
+		Err(panic) => Err(match panic.downcast::<WorkerError>() {
+		    Ok(error) => error.0,
+		    Err(_) => "transfer failed".to_owned(),
+		}),
+	    };
+	    (name, result)
+	}).collect()
+    });
+    std::panic::set_hook(Box::new(move |info| default_hook(info)));
+
+    let width = results.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max(4);
+    println!();
+    println!("{:<width$}  {}", style("Port").bold(), style("Result").bold(), width = width);
+    let mut failed = 0;
+    for (name, result) in &results {
+	match result {
+	    Ok(time) => println!("{:<width$}  {} ({:.1} s)", name, style("OK").green().bright(),
+				 time.as_secs_f32(), width = width),
+	    Err(e) => {
+		failed += 1;
+		println!("{:<width$}  {} {}", name, style("FAILED").red().bright(),
+			 e.trim_start_matches("Error: "), width = width);
+	    },
+	}
+    }
+    print!("File info:\n  ");
+    crate::hp_object::crc_and_output(path);
+    if failed > 0 {
+	crate::helpers::error_handler(format!("{} of {} transfers failed", failed, results.len()));
+    }
+}
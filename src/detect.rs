@@ -0,0 +1,85 @@
+// Find the calculator: try each serial port, and maybe each common
+// baud rate, and see if an XModem or Kermit server answers. This only
+// finds servers, because XRECV and RECV wait for us to start.
+
+use std::time::Duration;
+
+use console::style;
+
+// speeds of the HP 48 first, then the faster ones of the 49 series
+pub const COMMON_BAUDS: [u32; 8] = [9600, 4800, 2400, 1200, 19200, 38400, 57600, 115200];
+
+// The probes wait this long for an answer, instead of the usual
+// transfer timeout.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+// Which server answers on `port` at its current speed.
+pub fn probe(port: &mut Box<dyn serialport::SerialPort>) -> Option<&'static str> {
+    let timeout = port.timeout();
+    let _ = port.set_timeout(PROBE_TIMEOUT);
+    // a Kermit server ignores the XModem ping, but an XModem server
+    // might take part of a Kermit packet as a command, so XModem goes
+    // first
+    let found = if crate::xmodem::ping(port) {
+	Some("XModem server")
+    } else if crate::kermit::ping(port) {
+	Some("Kermit server")
+    } else {
+	None
+    };
+    // throw away anything else the calculator sent
+    std::thread::sleep(Duration::from_millis(100));
+    let _ = port.clear(serialport::ClearBuffer::All);
+    let _ = port.set_timeout(timeout);
+    return found;
+}
+
+// The first of `ports` where a server answers, for picking a port
+// automatically when there are several.
+pub fn find_calculator(ports: Vec<(String, Result<Box<dyn serialport::SerialPort>, String>)>) -> Option<String> {
+    for (name, port) in ports {
+	if let Ok(mut port) = port {
+	    if let Some(protocol) = probe(&mut port) {
+		println!("Found {} on {}", protocol, style(&name).green().bright());
+		return Some(name);
+	    }
+	}
+    }
+    return None;
+}
+
+// `alum detect`: try every port at every speed in `bauds` and print
+// what answered.
+pub fn detect(ports: Vec<(String, Result<Box<dyn serialport::SerialPort>, String>)>, bauds: &[u32]) {
+    let mut found = 0;
+    for (name, port) in ports {
+	let mut port = match port {
+	    Ok(p) => p,
+	    Err(e) => {
+		println!("{}: {}", name, style(e.trim_start_matches("Error: ")).red().bright());
+		continue;
+	    },
+	};
+	let mut answered = false;
+	for baud in bauds {
+	    if port.set_baud_rate(*baud).is_err() {
+		continue;
+	    }
+	    let pb = crate::helpers::get_spinner(format!("Trying {} at {} baud...", name, baud));
+	    let result = probe(&mut port);
+	    pb.finish_and_clear();
+	    if let Some(protocol) = result {
+		println!("{}: {} at {} baud", style(&name).green().bright(), protocol, baud);
+		answered = true;
+		found += 1;
+		break;
+	    }
+	}
+	if !answered {
+	    println!("{}: no answer", name);
+	}
+    }
+    if found == 0 {
+	crate::helpers::error_handler("No calculator found. Is the XModem or Kermit server running?".to_string());
+    }
+}
@@ -0,0 +1,298 @@
+// Structural diff of two objects. Both are parsed into trees of
+// composites, directories, and plain objects, and the trees are
+// compared, so a change shows up as "element 3 changed from 1.5 to 2."
+// instead of a list of differing bytes. Elements of composites are
+// matched up with a longest common subsequence, so inserting one
+// command into a program is reported as one insertion.
+
+use std::path::PathBuf;
+
+use console::style;
+
+use crate::hp_object;
+
+#[derive(PartialEq)]
+enum Node {
+    // a 5-nibble address in a composite, like DUP
+    Pointer(u32),
+    Composite { prolog: u32, elements: Vec<Node> },
+    Directory { entries: Vec<(String, Node)> },
+    Tagged { tag: String, inner: Box<Node> },
+    // anything we don't look inside
+    Atom { prolog: u32, nibs: Vec<u8> },
+}
+
+fn parse(nibs: &[u8]) -> Result<Node, String> {
+    let prolog = hp_object::get_prolog(nibs)?;
+    match prolog {
+	0x2a74 | 0x2ab8 | 0x2ada | 0x2d9d => {
+	    let mut elements: Vec<Node> = Vec::new();
+	    for (start, len) in hp_object::composite_elements(nibs)? {
+		let element = &nibs[start..start + len];
+		match hp_object::prolog_name(hp_object::get_prolog(element)?) {
+		    Some(_) => elements.push(parse(element)?),
+		    None => elements.push(Node::Pointer(hp_object::get_prolog(element)?)),
+		}
+	    }
+	    Ok(Node::Composite { prolog, elements })
+	},
+	0x2a96 => {
+	    let dir = hp_object::read_directory(nibs)?;
+	    let mut entries: Vec<(String, Node)> = Vec::new();
+	    for entry in dir.entries {
+		entries.push((entry.name, parse(&nibs[entry.start..entry.start + entry.len])?));
+	    }
+	    Ok(Node::Directory { entries })
+	},
+	0x2afc => {
+	    let tag_len = hp_object::read_nibbles(nibs, 5, 2)? as usize;
+	    let tag = hp_object::read_hp_string(nibs, 7, tag_len)?;
+	    Ok(Node::Tagged { tag, inner: Box::new(parse(&nibs[7 + tag_len * 2..])?) })
+	},
+	_ => {
+	    let len = hp_object::calc_object_size(nibs)? as usize;
+	    if len > nibs.len() {
+		return Err("object length is greater than file size; file may be corrupt".to_owned());
+	    }
+	    Ok(Node::Atom { prolog, nibs: nibs[..len].to_vec() })
+	},
+    }
+}
+
+fn type_name(prolog: u32) -> String {
+    match hp_object::object_type(prolog) {
+	Some((_, name)) => name.to_lowercase(),
+	None => hp_object::prolog_name(prolog).unwrap_or("unknown object").to_owned(),
+    }
+}
+
+// Short description of a node for the report.
+fn describe(node: &Node) -> String {
+    match node {
+	Node::Pointer(addr) => match crate::entries::entry_name(*addr) {
+	    Some(name) => name.to_owned(),
+	    None => format!("PTR {:05X}", addr),
+	},
+	Node::Composite { prolog, elements } => format!("{} of {} elements", type_name(*prolog), elements.len()),
+	Node::Directory { entries } => format!("directory of {} variables", entries.len()),
+	Node::Tagged { tag, inner } => format!(":{}: {}", tag, describe(inner)),
+	Node::Atom { prolog, nibs } => {
+	    // the SysRPL form is short for numbers, strings, and names
+	    match crate::sysrpl::decompile(nibs) {
+		Ok(lines) if lines.len() == 1 && !lines[0].starts_with('(') => lines[0].clone(),
+		_ => format!("{} of {} nibbles, CRC {}", type_name(*prolog), nibs.len(),
+			     hp_object::format_crc(hp_object::crc_nibbles(nibs))),
+	    }
+	},
+    }
+}
+
+struct Report {
+    // every difference, without colors
+    lines: Vec<String>,
+}
+
+impl Report {
+    fn line(&mut self, mark: &str, path: &str, text: String) {
+	let path = if path.is_empty() { "object" } else { path };
+	self.lines.push(format!("{} {}: {}", mark, path, text));
+	let mark = match mark {
+	    "+" => style(mark).green().bright(),
+	    "-" => style(mark).red().bright(),
+	    _ => style(mark).yellow().bright(),
+	};
+	println!("{} {}: {}", mark, style(path).bold(), text);
+    }
+}
+
+fn join(path: &str, segment: &str) -> String {
+    match path.is_empty() {
+	true => segment.to_owned(),
+	false => format!("{}/{}", path, segment),
+    }
+}
+
+// What kind of node this is, so that in a run of changed elements a
+// list is compared with a list instead of with whatever is next to it.
+fn same_kind(a: &Node, b: &Node) -> bool {
+    match (a, b) {
+	(Node::Pointer(_), Node::Pointer(_)) => true,
+	(Node::Composite { prolog: pa, .. }, Node::Composite { prolog: pb, .. }) => pa == pb,
+	(Node::Directory { .. }, Node::Directory { .. }) => true,
+	(Node::Tagged { .. }, Node::Tagged { .. }) => true,
+	(Node::Atom { prolog: pa, .. }, Node::Atom { prolog: pb, .. }) => pa == pb,
+	_ => false,
+    }
+}
+
+// Pairs of indexes of elements in `a` and `b` that `matches` says go
+// together, in order.
+fn common_elements(a: &[Node], b: &[Node], matches: fn(&Node, &Node) -> bool) -> Vec<(usize, usize)> {
+    // lengths[i][j] is the LCS length of a[i..] and b[j..]
+    let mut lengths = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+	for j in (0..b.len()).rev() {
+	    lengths[i][j] = match matches(&a[i], &b[j]) {
+		true => lengths[i + 1][j + 1] + 1,
+		false => lengths[i + 1][j].max(lengths[i][j + 1]),
+	    };
+	}
+    }
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+	if matches(&a[i], &b[j]) && lengths[i][j] == lengths[i + 1][j + 1] + 1 {
+	    pairs.push((i, j));
+	    i += 1;
+	    j += 1;
+	} else if lengths[i + 1][j] >= lengths[i][j + 1] {
+	    i += 1;
+	} else {
+	    j += 1;
+	}
+    }
+    return pairs;
+}
+
+// Elements that are in one run of changes: `a` and `b` start at
+// element `ia` and `ib` of their composites. Elements of the same kind
+// are compared in depth, and the rest were removed or added.
+fn diff_changed(a: &[Node], b: &[Node], (ia, ib): (usize, usize), path: &str, report: &mut Report) {
+    let mut pairs = common_elements(a, b, same_kind);
+    // a sentinel pair after the end flushes the last gap
+    pairs.push((a.len(), b.len()));
+    let (mut i, mut j) = (0, 0);
+    for (next_i, next_j) in pairs {
+	for k in i..next_i {
+	    report.line("-", &join(path, &format!("element {}", ia + k + 1)), format!("removed {}", describe(&a[k])));
+	}
+	for k in j..next_j {
+	    report.line("+", &join(path, &format!("element {}", ib + k + 1)), format!("added {}", describe(&b[k])));
+	}
+	if next_i < a.len() {
+	    diff_nodes(&a[next_i], &b[next_j], &join(path, &format!("element {}", ib + next_j + 1)), report);
+	}
+	i = next_i + 1;
+	j = next_j + 1;
+    }
+}
+
+fn diff_elements(a: &[Node], b: &[Node], path: &str, report: &mut Report) {
+    let mut pairs = common_elements(a, b, |x, y| x == y);
+    pairs.push((a.len(), b.len()));
+    let (mut i, mut j) = (0, 0);
+    for (next_i, next_j) in pairs {
+	diff_changed(&a[i..next_i], &b[j..next_j], (i, j), path, report);
+	i = next_i + 1;
+	j = next_j + 1;
+    }
+}
+
+fn diff_nodes(a: &Node, b: &Node, path: &str, report: &mut Report) {
+    if a == b {
+	return;
+    }
+    match (a, b) {
+	(Node::Composite { prolog: pa, elements: ea }, Node::Composite { prolog: pb, elements: eb }) if pa == pb => {
+	    diff_elements(ea, eb, path, report);
+	},
+	(Node::Directory { entries: ea }, Node::Directory { entries: eb }) => {
+	    // variables are matched by name, and their order doesn't matter
+	    for (name, node) in ea {
+		match eb.iter().find(|(n, _)| n == name) {
+		    Some((_, other)) => diff_nodes(node, other, &join(path, name), report),
+		    None => report.line("-", &join(path, name), format!("variable removed ({})", describe(node))),
+		}
+	    }
+	    for (name, node) in eb {
+		if !ea.iter().any(|(n, _)| n == name) {
+		    report.line("+", &join(path, name), format!("variable added ({})", describe(node)));
+		}
+	    }
+	},
+	(Node::Tagged { tag: ta, inner: ia }, Node::Tagged { tag: tb, inner: ib }) => {
+	    if ta != tb {
+		report.line("~", path, format!("tag changed from {:?} to {:?}", ta, tb));
+	    }
+	    diff_nodes(ia, ib, path, report);
+	},
+	_ => report.line("~", path, format!("changed from {} to {}", describe(a), describe(b))),
+    }
+}
+
+fn read_tree(path: &PathBuf) -> Result<Node, String> {
+    let (_, nibs) = hp_object::read_object_file(path)?;
+    return match parse(&nibs) {
+	Ok(node) => Ok(node),
+	Err(e) => Err(format!("{}: {}", path.display(), e)),
+    };
+}
+
+pub fn diff(a: &PathBuf, b: &PathBuf) {
+    let trees = read_tree(a).and_then(|ta| Ok((ta, read_tree(b)?)));
+    let (tree_a, tree_b) = match trees {
+	Ok(t) => t,
+	Err(e) => return crate::helpers::error_handler(format!("Error: {}", e)),
+    };
+
+    let mut report = Report { lines: Vec::new() };
+    diff_nodes(&tree_a, &tree_b, "", &mut report);
+    // exit status is like diff's: 0 if the same, 1 if different
+    let count = report.lines.len();
+    if count == 0 {
+	println!("{}", style("Objects are the same").green().bright());
+    } else {
+	crate::helpers::error_handler(format!("{} difference{}", count, if count == 1 { "" } else { "s" }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hp_object::nibbles;
+
+    const DUP: &str = "88130";
+    const SWAP: &str = "32230";
+    const DROP: &str = "44230";
+    const REAL_1_5: &str = "33920 000 000000000051 0";
+    const REAL_2: &str = "33920 000 000000000002 0";
+
+    // the differences between two programs made of `a` and `b`
+    fn program_diff(a: &[&str], b: &[&str]) -> Vec<String> {
+	let program = |elements: &[&str]| parse(&nibbles(&format!("D9D20 {} B2130", elements.join(" ")))).unwrap();
+	let mut report = Report { lines: Vec::new() };
+	diff_nodes(&program(a), &program(b), "", &mut report);
+	return report.lines;
+    }
+
+    #[test]
+    fn same_objects() {
+	assert!(program_diff(&[DUP, REAL_1_5], &[DUP, REAL_1_5]).is_empty());
+    }
+
+    #[test]
+    fn changed_element() {
+	assert_eq!(program_diff(&[DUP, REAL_1_5, SWAP], &[DUP, REAL_2, SWAP]),
+		   vec!["~ element 2: changed from % 1.5 to % 2"]);
+    }
+
+    #[test]
+    fn one_insertion() {
+	assert_eq!(program_diff(&[DUP, SWAP, REAL_2], &[DUP, DROP, SWAP, REAL_2]),
+		   vec!["+ element 2: added DROP"]);
+    }
+
+    #[test]
+    fn removal() {
+	assert_eq!(program_diff(&[DUP, SWAP], &[SWAP]), vec!["- element 1: removed DUP"]);
+    }
+
+    #[test]
+    fn tag_change() {
+	let a = parse(&nibbles(&format!("CFA20 10 14 {}", REAL_2))).unwrap();
+	let b = parse(&nibbles(&format!("CFA20 10 24 {}", REAL_2))).unwrap();
+	let mut report = Report { lines: Vec::new() };
+	diff_nodes(&a, &b, "", &mut report);
+	assert_eq!(report.lines, vec!["~ object: tag changed from \"A\" to \"B\""]);
+    }
+}
@@ -0,0 +1,487 @@
+// Saturn disassembler for code objects (DOCODE, prolog 0x2dcc). A code
+// object is the prolog, a 5-nibble size (which includes itself), and
+// then machine code that starts running at the nibble after the size.
+// Mnemonics are the ones in HP's SASM documentation, and addresses
+// are offsets from the start of the object.
+
+// Opcodes are one to several nibbles long, and the first nibbles
+// select a group. Many groups take a field selector nibble:
+/*
+ * 0 P, 1 WP, 2 XS, 3 X, 4 S, 5 M, 6 B, 7 W, and F A in some groups
+ */
+// Jumps are relative to the address of their offset field, except
+// GOSUB and GOSUBL, which are relative to the end of the instruction.
+
+use std::path::PathBuf;
+
+use console::style;
+
+use crate::hp_object;
+
+const FIELDS: [&str; 8] = ["P", "WP", "XS", "X", "S", "M", "B", "W"];
+
+// tests in group 8A, and 9 with field 0-7
+const TESTS_EQ: [&str; 16] = [
+    "?A=B", "?B=C", "?A=C", "?C=D", "?A#B", "?B#C", "?A#C", "?C#D",
+    "?A=0", "?B=0", "?C=0", "?D=0", "?A#0", "?B#0", "?C#0", "?D#0"];
+// tests in group 8B, and 9 with field 8-F
+const TESTS_CMP: [&str; 16] = [
+    "?A>B", "?B>C", "?C>A", "?D>C", "?A<B", "?B<C", "?C<A", "?D<C",
+    "?A>=B", "?B>=C", "?C>=A", "?D>=C", "?A<=B", "?B<=C", "?C<=A", "?D<=C"];
+// group A with field 0-7, and C
+const ARITH_ADD: [&str; 16] = [
+    "A=A+B", "B=B+C", "C=C+A", "D=D+C", "A=A+A", "B=B+B", "C=C+C", "D=D+D",
+    "B=B+A", "C=C+B", "A=A+C", "C=C+D", "A=A-1", "B=B-1", "C=C-1", "D=D-1"];
+// group A with field 8-F, and D
+const ARITH_COPY: [&str; 16] = [
+    "A=0", "B=0", "C=0", "D=0", "A=B", "B=C", "C=A", "D=C",
+    "B=A", "C=B", "A=C", "C=D", "ABEX", "BCEX", "ACEX", "CDEX"];
+// group B with field 0-7, and E
+const ARITH_SUB: [&str; 16] = [
+    "A=A-B", "B=B-C", "C=C-A", "D=D-C", "A=A+1", "B=B+1", "C=C+1", "D=D+1",
+    "B=B-A", "C=C-B", "A=A-C", "C=C-D", "A=B-A", "B=C-B", "C=A-C", "D=C-D"];
+// group B with field 8-F, and F
+const ARITH_SHIFT: [&str; 16] = [
+    "ASL", "BSL", "CSL", "DSL", "ASR", "BSR", "CSR", "DSR",
+    "A=-A", "B=-B", "C=-C", "D=-D", "A=-A-1", "B=-B-1", "C=-C-1", "D=-D-1"];
+// group 0E
+const LOGIC: [&str; 16] = [
+    "A=A&B", "B=B&C", "C=C&A", "D=D&C", "B=B&A", "C=C&B", "A=A&C", "C=C&D",
+    "A=A!B", "B=B!C", "C=C!A", "D=D!C", "B=B!A", "C=C!B", "A=A!C", "C=C!D"];
+// group 13
+const DATA_POINTERS: [&str; 16] = [
+    "D0=A", "D1=A", "AD0EX", "AD1EX", "D0=C", "D1=C", "CD0EX", "CD1EX",
+    "D0=AS", "D1=AS", "AD0XS", "AD1XS", "D0=CS", "D1=CS", "CD0XS", "CD1XS"];
+// groups 14 and 15
+const MEMORY: [&str; 8] = ["DAT0=A", "DAT1=A", "A=DAT0", "A=DAT1", "DAT0=C", "DAT1=C", "C=DAT0", "C=DAT1"];
+
+// Field selector for groups that use F for the A field.
+fn field_name(f: u8) -> Option<&'static str> {
+    match f {
+	0..=7 => Some(FIELDS[f as usize]),
+	0xf => Some("A"),
+	_ => None,
+    }
+}
+
+// Scratch register operation on register `r`, where 0-4 use the
+// `a_form` with A and 8-C use the `c_form` with C.
+fn scratch(r: u8, a_form: &str, c_form: &str) -> Option<String> {
+    match r {
+	0..=4 => Some(a_form.replace("{}", &r.to_string())),
+	8..=0xc => Some(c_form.replace("{}", &(r - 8).to_string())),
+	_ => None,
+    }
+}
+
+// Decodes one instruction at a time from a list of nibbles.
+struct Decoder<'a> {
+    nibs: &'a [u8],
+}
+
+impl Decoder<'_> {
+    fn nib(&self, addr: usize) -> Option<u8> {
+	self.nibs.get(addr).copied()
+    }
+
+    // Unsigned value of `count` nibbles at `addr`, low nibble first.
+    fn value(&self, addr: usize, count: usize) -> Option<u32> {
+	if addr + count > self.nibs.len() {
+	    return None;
+	}
+	return hp_object::read_nibbles(self.nibs, addr, count).ok();
+    }
+
+    // Two's complement value of `count` nibbles at `addr`.
+    fn signed(&self, addr: usize, count: usize) -> Option<i64> {
+	let v = self.value(addr, count)? as i64;
+	let half = 1i64 << (count * 4 - 1);
+	return Some(if v >= half { v - (half << 1) } else { v });
+    }
+
+    // Hex constant of `count` nibbles at `addr`, written most
+    // significant nibble first like the assembler wants it.
+    fn hex(&self, addr: usize, count: usize) -> Option<String> {
+	if addr + count > self.nibs.len() {
+	    return None;
+	}
+	return Some(self.nibs[addr..addr + count].iter().rev().map(|n| format!("{:X}", n)).collect());
+    }
+
+    fn target(base: usize, offset: i64) -> String {
+	format!("{:05X}", (base as i64 + offset) as u32 & 0xfffff)
+    }
+
+    // A test is followed by a 2-nibble GOYES offset at `addr`, or
+    // RTNYES if the offset is 0.
+    fn goyes(&self, test: String, addr: usize) -> Option<(usize, String)> {
+	let offset = self.signed(addr, 2)?;
+	let text = match offset {
+	    0 => format!("{}\nRTNYES", test),
+	    _ => format!("{}\nGOYES\t{}", test, Decoder::target(addr, offset)),
+	};
+	return Some((addr + 2, text));
+    }
+
+    // Decode the instruction at `pc`. Returns the address of the next
+    // instruction and the text, or None if the opcode is invalid or
+    // runs past the end of the code.
+    fn decode(&self, pc: usize) -> Option<(usize, String)> {
+	let n0 = self.nib(pc)?;
+	let simple = |len: usize, text: &str| Some((pc + len, text.to_owned()));
+	match n0 {
+	    0x0 => {
+		let n1 = self.nib(pc + 1)?;
+		match n1 {
+		    0xe => {
+			let f = field_name(self.nib(pc + 2)?)?;
+			let op = LOGIC[self.nib(pc + 3)? as usize];
+			Some((pc + 4, format!("{}\t{}", op, f)))
+		    },
+		    _ => simple(2, ["RTNSXM", "RTN", "RTNSC", "RTNCC", "SETHEX", "SETDEC", "RSTK=C", "C=RSTK",
+				    "CLRST", "C=ST", "ST=C", "CSTEX", "P=P+1", "P=P-1", "", "RTI"][n1 as usize]),
+		}
+	    },
+	    0x1 => self.decode_1(pc),
+	    0x2 => Some((pc + 2, format!("P=\t{}", self.nib(pc + 1)?))),
+	    0x3 => {
+		let count = self.nib(pc + 1)? as usize + 1;
+		Some((pc + 2 + count, format!("LCHEX\t{}", self.hex(pc + 2, count)?)))
+	    },
+	    0x4 | 0x5 => {
+		let offset = self.signed(pc + 1, 2)?;
+		let (jump, rtn) = if n0 == 4 { ("GOC", "RTNC") } else { ("GONC", "RTNNC") };
+		match offset {
+		    0 => simple(3, rtn),
+		    _ => Some((pc + 3, format!("{}\t{}", jump, Decoder::target(pc + 1, offset)))),
+		}
+	    },
+	    0x6 => Some((pc + 4, format!("GOTO\t{}", Decoder::target(pc + 1, self.signed(pc + 1, 3)?)))),
+	    0x7 => Some((pc + 4, format!("GOSUB\t{}", Decoder::target(pc + 4, self.signed(pc + 1, 3)?)))),
+	    0x8 => self.decode_8(pc),
+	    0x9 => {
+		let f = self.nib(pc + 1)?;
+		let x = self.nib(pc + 2)? as usize;
+		let test = match f {
+		    0..=7 => TESTS_EQ[x],
+		    _ => TESTS_CMP[x],
+		};
+		self.goyes(format!("{}\t{}", test, FIELDS[(f & 7) as usize]), pc + 3)
+	    },
+	    0xa | 0xb => {
+		let f = self.nib(pc + 1)?;
+		let x = self.nib(pc + 2)? as usize;
+		let op = match (n0, f < 8) {
+		    (0xa, true) => ARITH_ADD[x],
+		    (0xa, false) => ARITH_COPY[x],
+		    (_, true) => ARITH_SUB[x],
+		    (_, false) => ARITH_SHIFT[x],
+		};
+		Some((pc + 3, format!("{}\t{}", op, FIELDS[(f & 7) as usize])))
+	    },
+	    // the same as A and B, with the A field
+	    _ => {
+		let x = self.nib(pc + 1)? as usize;
+		let op = [ARITH_ADD, ARITH_COPY, ARITH_SUB, ARITH_SHIFT][(n0 - 0xc) as usize][x];
+		Some((pc + 2, format!("{}\tA", op)))
+	    },
+	}
+    }
+
+    fn decode_1(&self, pc: usize) -> Option<(usize, String)> {
+	let n1 = self.nib(pc + 1)?;
+	match n1 {
+	    0x0 => Some((pc + 3, scratch(self.nib(pc + 2)?, "R{}=A", "R{}=C")?)),
+	    0x1 => Some((pc + 3, scratch(self.nib(pc + 2)?, "A=R{}", "C=R{}")?)),
+	    0x2 => Some((pc + 3, scratch(self.nib(pc + 2)?, "AR{}EX", "CR{}EX")?)),
+	    0x3 => Some((pc + 3, DATA_POINTERS[self.nib(pc + 2)? as usize].to_owned())),
+	    0x4 => {
+		let x = self.nib(pc + 2)?;
+		let field = if x < 8 { "A" } else { "B" };
+		Some((pc + 3, format!("{}\t{}", MEMORY[(x & 7) as usize], field)))
+	    },
+	    0x5 => {
+		let x = self.nib(pc + 2)?;
+		let y = self.nib(pc + 3)?;
+		let field = match x {
+		    0..=7 => FIELDS[y as usize & 7].to_owned(),
+		    _ => (y + 1).to_string(),
+		};
+		Some((pc + 4, format!("{}\t{}", MEMORY[(x & 7) as usize], field)))
+	    },
+	    0x6 => Some((pc + 3, format!("D0=D0+\t{}", self.nib(pc + 2)? + 1))),
+	    0x7 => Some((pc + 3, format!("D1=D1+\t{}", self.nib(pc + 2)? + 1))),
+	    0x8 => Some((pc + 3, format!("D0=D0-\t{}", self.nib(pc + 2)? + 1))),
+	    0xc => Some((pc + 3, format!("D1=D1-\t{}", self.nib(pc + 2)? + 1))),
+	    _ => {
+		// 9 A B load D0 with 2, 4, or 5 nibbles, D E F load D1
+		let register = if n1 < 0xd { "D0" } else { "D1" };
+		let count = [2, 4, 5][((n1 - 9) % 4) as usize];
+		Some((pc + 2 + count, format!("{}=({})\t{}", register, count, self.hex(pc + 2, count)?)))
+	    },
+	}
+    }
+
+    fn decode_8(&self, pc: usize) -> Option<(usize, String)> {
+	let n1 = self.nib(pc + 1)?;
+	match n1 {
+	    0x0 => self.decode_80(pc),
+	    0x1 => self.decode_81(pc),
+	    0x2 => {
+		let n = self.nib(pc + 2)?;
+		let text = match n {
+		    1 => "XM=0".to_owned(),
+		    2 => "SB=0".to_owned(),
+		    4 => "SR=0".to_owned(),
+		    8 => "MP=0".to_owned(),
+		    0xf => "CLRHST".to_owned(),
+		    _ => format!("HST=0\t{}", n),
+		};
+		Some((pc + 3, text))
+	    },
+	    0x3 => self.goyes(format!("?HST=0\t{}", self.nib(pc + 2)?), pc + 3),
+	    0x4 => Some((pc + 3, format!("ST=0\t{}", self.nib(pc + 2)?))),
+	    0x5 => Some((pc + 3, format!("ST=1\t{}", self.nib(pc + 2)?))),
+	    0x6 => self.goyes(format!("?ST=0\t{}", self.nib(pc + 2)?), pc + 3),
+	    0x7 => self.goyes(format!("?ST=1\t{}", self.nib(pc + 2)?), pc + 3),
+	    0x8 => self.goyes(format!("?P#\t{}", self.nib(pc + 2)?), pc + 3),
+	    0x9 => self.goyes(format!("?P=\t{}", self.nib(pc + 2)?), pc + 3),
+	    0xa => self.goyes(format!("{}\tA", TESTS_EQ[self.nib(pc + 2)? as usize]), pc + 3),
+	    0xb => self.goyes(format!("{}\tA", TESTS_CMP[self.nib(pc + 2)? as usize]), pc + 3),
+	    0xc => Some((pc + 6, format!("GOLONG\t{}", Decoder::target(pc + 2, self.signed(pc + 2, 4)?)))),
+	    0xd => Some((pc + 7, format!("GOVLNG\t{}", self.hex(pc + 2, 5)?))),
+	    0xe => Some((pc + 6, format!("GOSUBL\t{}", Decoder::target(pc + 6, self.signed(pc + 2, 4)?)))),
+	    _ => Some((pc + 7, format!("GOSBVL\t{}", self.hex(pc + 2, 5)?))),
+	}
+    }
+
+    fn decode_80(&self, pc: usize) -> Option<(usize, String)> {
+	let n2 = self.nib(pc + 2)?;
+	let simple = |text: &str| Some((pc + 3, text.to_owned()));
+	match n2 {
+	    0x0 => simple("OUT=CS"),
+	    0x1 => simple("OUT=C"),
+	    0x2 => simple("A=IN"),
+	    0x3 => simple("C=IN"),
+	    0x4 => simple("UNCNFG"),
+	    0x5 => simple("CONFIG"),
+	    0x6 => simple("C=ID"),
+	    0x7 => simple("SHUTDN"),
+	    0x8 => {
+		let n3 = self.nib(pc + 3)?;
+		let bit = |op: &str| Some((pc + 5, format!("{}\t{}", op, self.nib(pc + 4)?)));
+		match n3 {
+		    0x0 => Some((pc + 4, "INTON".to_owned())),
+		    0x1 => Some((pc + 5, "RSI".to_owned())),
+		    0x2 => {
+			let count = self.nib(pc + 4)? as usize + 1;
+			Some((pc + 5 + count, format!("LAHEX\t{}", self.hex(pc + 5, count)?)))
+		    },
+		    0x3 => Some((pc + 4, "BUSCB".to_owned())),
+		    0x4 => bit("ABIT=0"),
+		    0x5 => bit("ABIT=1"),
+		    0x6 => self.goyes(format!("?ABIT=0\t{}", self.nib(pc + 4)?), pc + 5),
+		    0x7 => self.goyes(format!("?ABIT=1\t{}", self.nib(pc + 4)?), pc + 5),
+		    0x8 => bit("CBIT=0"),
+		    0x9 => bit("CBIT=1"),
+		    0xa => self.goyes(format!("?CBIT=0\t{}", self.nib(pc + 4)?), pc + 5),
+		    0xb => self.goyes(format!("?CBIT=1\t{}", self.nib(pc + 4)?), pc + 5),
+		    0xc => Some((pc + 4, "PC=(A)".to_owned())),
+		    0xd => Some((pc + 4, "BUSCD".to_owned())),
+		    0xe => Some((pc + 4, "PC=(C)".to_owned())),
+		    _ => Some((pc + 4, "INTOFF".to_owned())),
+		}
+	    },
+	    0x9 => simple("C+P+1"),
+	    0xa => simple("RESET"),
+	    0xb => simple("BUSCC"),
+	    0xc => Some((pc + 4, format!("C=P\t{}", self.nib(pc + 3)?))),
+	    0xd => Some((pc + 4, format!("P=C\t{}", self.nib(pc + 3)?))),
+	    0xe => simple("SREQ?"),
+	    _ => Some((pc + 4, format!("CPEX\t{}", self.nib(pc + 3)?))),
+	}
+    }
+
+    fn decode_81(&self, pc: usize) -> Option<(usize, String)> {
+	let n2 = self.nib(pc + 2)?;
+	match n2 {
+	    0x0..=0x7 => Some((pc + 3, ["ASLC", "BSLC", "CSLC", "DSLC", "ASRC", "BSRC", "CSRC", "DSRC"][n2 as usize].to_owned())),
+	    0x8 => {
+		// add or subtract a constant: field, register, constant - 1
+		let f = field_name(self.nib(pc + 3)?)?;
+		let r = self.nib(pc + 4)?;
+		let n = self.nib(pc + 5)? + 1;
+		let reg = ["A", "B", "C", "D"][(r & 3) as usize];
+		let op = if r < 8 { "+" } else { "-" };
+		Some((pc + 6, format!("{}={}{}CON\t{},{}", reg, reg, op, f, n)))
+	    },
+	    0x9 => {
+		let f = field_name(self.nib(pc + 3)?)?;
+		let r = self.nib(pc + 4)?;
+		Some((pc + 5, format!("{}SRB.F\t{}", ["A", "B", "C", "D"][(r & 3) as usize], f)))
+	    },
+	    0xa => {
+		let f = field_name(self.nib(pc + 3)?)?;
+		let op = self.nib(pc + 4)?;
+		let r = self.nib(pc + 5)?;
+		let text = match op {
+		    0 => scratch(r, "R{}=A.F", "R{}=C.F")?,
+		    1 => scratch(r, "A=R{}.F", "C=R{}.F")?,
+		    2 => scratch(r, "AR{}EX.F", "CR{}EX.F")?,
+		    _ => return None,
+		};
+		Some((pc + 6, format!("{}\t{}", text, f)))
+	    },
+	    0xb => {
+		let text = match self.nib(pc + 3)? {
+		    2 => "PC=A",
+		    3 => "PC=C",
+		    4 => "A=PC",
+		    5 => "C=PC",
+		    6 => "APCEX",
+		    7 => "CPCEX",
+		    _ => return None,
+		};
+		Some((pc + 4, text.to_owned()))
+	    },
+	    _ => Some((pc + 3, ["ASRB", "BSRB", "CSRB", "DSRB"][(n2 - 0xc) as usize].to_owned())),
+	}
+    }
+}
+
+// Disassemble `nibs` from `start` to `end`, one instruction per line
+// with its address and raw nibbles.
+pub fn disassemble(nibs: &[u8], start: usize, end: usize) -> Vec<String> {
+    let mut output: Vec<String> = Vec::new();
+    let decoder = Decoder { nibs: &nibs[..end] };
+    let mut pc = start;
+    while pc < end {
+	let (next, text) = match decoder.decode(pc) {
+	    Some(d) if !d.1.is_empty() => d,
+	    // not a valid instruction, so show it as data
+	    _ => (pc + 1, format!("NIBHEX\t{:X}", nibs[pc])),
+	};
+	let raw: String = nibs[pc..next].iter().map(|n| format!("{:X}", n)).collect();
+	// line up the operands, and put the GOYES of a test on a
+	// second line
+	let lines: Vec<String> = text.split('\n').map(|line| match line.split_once('\t') {
+	    Some((op, args)) => format!("{:<8}{}", op, args),
+	    None => line.to_owned(),
+	}).collect();
+	output.push(format!("{} {:<10} {}", style(format!("{:05X}", pc)).dim(), raw, lines[0]));
+	for line in &lines[1..] {
+	    output.push(format!("{:5} {:<10} {}", "", "", line));
+	}
+	pc = next;
+    }
+    return output;
+}
+
+// Disassemble every code object in the object at the start of `nibs`,
+// looking inside composites, directories, and libraries. `name` says
+// where the object is, and `found` counts code objects.
+fn disassemble_all(nibs: &[u8], name: &str, found: &mut usize) -> Result<(), String> {
+    let prolog = hp_object::get_prolog(nibs)?;
+    match prolog {
+	0x2dcc => {
+	    let len = hp_object::calc_object_size(nibs)? as usize;
+	    if len > nibs.len() {
+		return Err("code object length is greater than file size; file may be corrupt".to_owned());
+	    }
+	    println!("{} {}, {} nibbles of code:",
+		     style("Code object").green().bright(), style(name).yellow().bright(), len - 10);
+	    // skip prolog and size
+	    for line in disassemble(&nibs[..len], 10, len) {
+		println!("{}", line);
+	    }
+	    *found += 1;
+	},
+	0x2d9d | 0x2a74 | 0x2ab8 | 0x2ada => {
+	    for (pos, (start, _)) in hp_object::composite_elements(nibs)?.iter().enumerate() {
+		disassemble_all(&nibs[*start..], &format!("{} element {}", name, pos + 1), found)?;
+	    }
+	},
+	0x2a96 => {
+	    let dir = hp_object::read_directory(nibs)?;
+	    for entry in &dir.entries {
+		disassemble_all(&nibs[entry.start..entry.start + entry.len],
+				&format!("{}/{}", name, entry.name), found)?;
+	    }
+	},
+	0x2b40 => {
+	    let lib = crate::library::read_library(nibs)?;
+	    for (cmd, (start, len)) in lib.command_objects.iter().enumerate() {
+		let cmd_name = match &lib.commands[cmd] {
+		    Some(n) => n.to_owned(),
+		    None => format!("XLIB {} {}", lib.number, cmd),
+		};
+		disassemble_all(&nibs[*start..start + len], &cmd_name, found)?;
+	    }
+	    if let Some((start, len)) = lib.config {
+		disassemble_all(&nibs[start..start + len], "config", found)?;
+	    }
+	},
+	_ => {},
+    }
+    return Ok(());
+}
+
+pub fn disasm(path: &PathBuf, sysrpl: &bool) {
+    let (_, nibs) = match hp_object::read_object_file(path) {
+	Ok(f) => f,
+	Err(e) => return crate::helpers::error_handler(format!("Error: {}", e)),
+    };
+    if *sysrpl {
+	match crate::sysrpl::decompile(&nibs) {
+	    Ok(lines) => lines.iter().for_each(|line| println!("{}", line)),
+	    Err(e) => crate::helpers::error_handler(format!("Error: {}", e)),
+	}
+	return;
+    }
+    let name = path.file_name().unwrap().to_string_lossy().to_string();
+    let mut found = 0;
+    if let Err(e) = disassemble_all(&nibs, &name, &mut found) {
+	crate::helpers::error_handler(format!("Error: {}", e));
+    }
+    if found == 0 {
+	crate::helpers::error_handler("Error: no code objects found".to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hp_object::nibbles;
+
+    // instructions in `code`, starting at address 0, without the raw
+    // nibbles column
+    fn listing(code: &str) -> Vec<String> {
+	console::set_colors_enabled(false);
+	let nibs = nibbles(code);
+	return disassemble(&nibs, 0, nibs.len()).iter()
+	    .map(|line| line[17..].to_owned())
+	    .collect();
+    }
+
+    #[test]
+    fn memory_and_arithmetic() {
+	assert_eq!(listing("142 164 E6 20"), vec!["A=DAT0  A", "D0=D0+  5", "C=C+1   A", "P=      0"]);
+    }
+
+    #[test]
+    fn constants_are_shown_most_significant_first() {
+	// stored low nibble first
+	assert_eq!(listing("3412345 31D5"), vec!["LCHEX   54321", "LCHEX   5D"]);
+    }
+
+    #[test]
+    fn jumps_are_relative_to_their_offset() {
+	// GOYES offset at 3, GOTO offset at 6
+	assert_eq!(listing("8A050 6300 01"), vec!["?A=B    A", "GOYES   00008", "GOTO    00009", "RTN"]);
+    }
+
+    #[test]
+    fn cut_off_instruction_is_data() {
+	assert_eq!(listing("341"), vec!["NIBHEX  3", "NIBHEX  4", "NIBHEX  1"]);
+    }
+}
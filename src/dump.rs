@@ -0,0 +1,273 @@
+// Annotated nibble dump of an object, for finding out by eye why a
+// file won't parse. Every field is printed on its own line as the
+// nibble offset after the header, the raw nibbles in file order, and
+// what the field means:
+/*
+ * 00000 D9D20            prolog DOCOL (Program)
+ * 00005 88130              pointer DUP
+ * 0000A B2130            SEMI end marker
+ */
+// Nibbles are shown in the order they are stored, which is backwards
+// from how addresses and sizes are written (88130 is 0x03188).
+
+use std::path::PathBuf;
+
+use console::style;
+
+use crate::hp_object;
+
+// raw nibbles shown per line; longer fields continue on more lines
+const RAW_WIDTH: usize = 16;
+
+fn raw(nibs: &[u8]) -> String {
+    nibs.iter().map(|n| format!("{:X}", n)).collect()
+}
+
+// Print the field of `count` nibbles at `pos` with `note`, indented by
+// `depth`. Returns where the next field starts.
+fn field(nibs: &[u8], pos: usize, count: usize, depth: usize, note: &str) -> Result<usize, String> {
+    if pos + count > nibs.len() {
+	return Err(format!("{} at nibble {:05X} runs past the end of the file", note, pos));
+    }
+    let data = &nibs[pos..pos + count];
+    let mut first = true;
+    for (i, chunk) in data.chunks(RAW_WIDTH).enumerate() {
+	let offset = style(format!("{:05X}", pos + i * RAW_WIDTH)).dim();
+	if first {
+	    println!("{} {:<width$} {}{}", offset, raw(chunk), "  ".repeat(depth), note, width = RAW_WIDTH);
+	    first = false;
+	} else {
+	    println!("{} {}", offset, raw(chunk));
+	}
+    }
+    return Ok(pos + count);
+}
+
+fn value(nibs: &[u8], pos: usize, count: usize) -> Result<u32, String> {
+    if pos + count > nibs.len() {
+	return Err(format!("field at nibble {:05X} runs past the end of the file", pos));
+    }
+    return hp_object::read_nibbles(nibs, pos, count);
+}
+
+// Size field of a SizeNext object at `pos`, then its body. Returns
+// the end of the object.
+fn sized_body(nibs: &[u8], start: usize, prolog: u32, depth: usize) -> Result<usize, String> {
+    let size = value(nibs, start + 5, 5)? as usize;
+    let end = start + 5 + size;
+    let mut pos = field(nibs, start + 5, 5, depth, &format!("size {} nibbles, object ends at {:05X}", size, end))?;
+    if size < 5 {
+	return Err(format!("size field at nibble {:05X} is less than its own length", start + 5));
+    }
+    if end > nibs.len() {
+	return Err(format!("object length is greater than file size (ends at {:05X}, file has {:05X} nibbles)",
+			   end, nibs.len()));
+    }
+    match prolog {
+	0x2a2c => {
+	    let text = hp_object::read_hp_string(nibs, pos, (end - pos) / 2)?;
+	    pos = field(nibs, pos, end - pos, depth, &format!("text {:?}", text))?;
+	},
+	0x2b1e if end - pos >= 10 => {
+	    pos = field(nibs, pos, 5, depth, &format!("height {}", value(nibs, pos, 5)?))?;
+	    pos = field(nibs, pos, 5, depth, &format!("width {}", value(nibs, pos, 5)?))?;
+	    pos = field(nibs, pos, end - pos, depth, "bitmap")?;
+	},
+	0x29e8 if end - pos >= 10 => {
+	    let elem_prolog = value(nibs, pos, 5)?;
+	    pos = field(nibs, pos, 5, depth, &format!("element prolog {}",
+						     hp_object::prolog_name(elem_prolog).unwrap_or("unknown")))?;
+	    let dims = value(nibs, pos, 5)?;
+	    pos = field(nibs, pos, 5, depth, &format!("{} dimensions", dims))?;
+	    for d in 0..dims {
+		pos = field(nibs, pos, 5, depth, &format!("dimension {}: {}", d + 1, value(nibs, pos, 5)?))?;
+	    }
+	    if pos < end {
+		pos = field(nibs, pos, end - pos, depth, "elements")?;
+	    }
+	},
+	0x2dcc => pos = field(nibs, pos, end - pos, depth, "machine code")?,
+	_ => {
+	    if pos < end {
+		pos = field(nibs, pos, end - pos, depth, "data")?;
+	    }
+	},
+    }
+    return Ok(pos);
+}
+
+// ASCIC name (length, then characters) at `pos`.
+fn ascic(nibs: &[u8], pos: usize, depth: usize) -> Result<usize, String> {
+    let len = value(nibs, pos, 2)? as usize;
+    let pos = field(nibs, pos, 2, depth, &format!("ASCIC length {}", len))?;
+    let name = hp_object::read_hp_string(nibs, pos, len)?;
+    return field(nibs, pos, len * 2, depth, &format!("name {:?}", name));
+}
+
+// ASCIX name (length, characters, length again) at `pos`.
+fn ascix(nibs: &[u8], pos: usize, depth: usize) -> Result<usize, String> {
+    let len = value(nibs, pos, 2)? as usize;
+    let pos = field(nibs, pos, 2, depth, &format!("ASCIX length {}", len))?;
+    let name = hp_object::read_hp_string(nibs, pos, len)?;
+    let pos = field(nibs, pos, len * 2, depth, &format!("name {:?}", name))?;
+    let trailing = value(nibs, pos, 2)? as usize;
+    let note = match trailing == len {
+	true => format!("ASCIX length {} again", trailing),
+	false => format!("ASCIX length {} DOES NOT MATCH {}", trailing, len),
+    };
+    return field(nibs, pos, 2, depth, &note);
+}
+
+fn directory(nibs: &[u8], start: usize, depth: usize) -> Result<usize, String> {
+    let lib = value(nibs, start + 5, 3)?;
+    let lib_note = match lib {
+	0x7ff => "no attached library".to_owned(),
+	_ => format!("attached library {}", lib),
+    };
+    let mut pos = field(nibs, start + 5, 3, depth, &lib_note)?;
+    let offset = value(nibs, pos, 5)? as usize;
+    let last_name = pos + offset;
+    pos = match offset {
+	0 => field(nibs, pos, 5, depth, "offset 0, empty directory")?,
+	_ => field(nibs, pos, 5, depth, &format!("offset to last name, at {:05X}", last_name))?,
+    };
+    pos = field(nibs, pos, 5, depth, "link 00000 before first name")?;
+    if offset == 0 {
+	return Ok(pos);
+    }
+
+    loop {
+	let name_start = pos;
+	pos = ascix(nibs, pos, depth + 1)?;
+	pos = dump_object(nibs, pos, depth + 1)?;
+	if name_start == last_name {
+	    return Ok(pos);
+	} else if name_start > last_name {
+	    return Err(format!("directory offset points to {:05X}, which is not a variable name", last_name));
+	}
+	let link = value(nibs, pos, 5)? as usize;
+	pos = field(nibs, pos, 5, depth, &format!("link back to name at {:05X}", pos.wrapping_sub(link)))?;
+    }
+}
+
+// Elements of a composite starting at `start`, until the SEMI.
+fn composite(nibs: &[u8], start: usize, depth: usize) -> Result<usize, String> {
+    let mut pos = start + 5;
+    loop {
+	if pos + 5 > nibs.len() {
+	    return Err(format!("no end marker (0x0312B) found for object at {:05X}", start));
+	}
+	let addr = value(nibs, pos, 5)?;
+	if addr == 0x312b {
+	    return field(nibs, pos, 5, depth, "SEMI end marker");
+	}
+	pos = match hp_object::prolog_name(addr) {
+	    Some(_) => dump_object(nibs, pos, depth + 1)?,
+	    None => {
+		let note = match crate::entries::entry_name(addr) {
+		    Some(name) => format!("pointer {}", name),
+		    None => format!("pointer to {:05X}", addr),
+		};
+		field(nibs, pos, 5, depth + 1, &note)?
+	    },
+	};
+    }
+}
+
+// Print the object at `start` and everything in it. Returns where the
+// object ends.
+fn dump_object(nibs: &[u8], start: usize, depth: usize) -> Result<usize, String> {
+    let prolog = value(nibs, start, 5)?;
+    let name = match hp_object::prolog_name(prolog) {
+	Some(n) => n,
+	None => return Err(format!("unknown prolog {:05X} at nibble {:05X}", prolog, start)),
+    };
+    let type_name = hp_object::object_type(prolog).map(|(_, t)| t).unwrap_or("Unknown Object");
+    let pos = field(nibs, start, 5, depth, &format!("prolog {} ({})", name, type_name))?;
+
+    match prolog {
+	0x2911 => {
+	    let n = value(nibs, pos, 5)?;
+	    field(nibs, pos, 5, depth, &format!("value #{:X}h ({})", n, n))
+	},
+	0x2933 | 0x2955 | 0x2977 | 0x299d => {
+	    let len = crate::hp_real::HpNumber::body_len(prolog).unwrap();
+	    let note = match crate::hp_real::HpNumber::from_body(&nibs[pos.min(nibs.len())..], prolog) {
+		Ok(n) => format!("value {}", n),
+		Err(e) => format!("bad number: {}", e),
+	    };
+	    field(nibs, pos, len, depth, &note)
+	},
+	0x29bf => {
+	    let c = value(nibs, pos, 2)?;
+	    field(nibs, pos, 2, depth, &format!("character {:?}", crate::helpers::hp_char_to_char(c as u8)))
+	},
+	0x2e92 => {
+	    let pos = field(nibs, pos, 3, depth, &format!("library {}", value(nibs, pos, 3)?))?;
+	    field(nibs, pos, 3, depth, &format!("command {}", value(nibs, pos, 3)?))
+	},
+	0x2e48 | 0x2e6d => ascic(nibs, pos, depth),
+	0x2afc => {
+	    let pos = ascic(nibs, pos, depth)?;
+	    dump_object(nibs, pos, depth + 1)
+	},
+	0x2a74 | 0x2ab8 | 0x2ada | 0x2d9d => composite(nibs, start, depth),
+	0x2a96 => directory(nibs, start, depth),
+	_ => sized_body(nibs, start, prolog, depth),
+    }
+}
+
+pub fn dump(path: &PathBuf) {
+    let (romrev, nibs) = match hp_object::read_object_file(path) {
+	Ok(f) => f,
+	Err(e) => return crate::helpers::error_handler(format!("Error: {}", e)),
+    };
+    println!("{} {:<width$} header, ROM revision {}", style("-----").dim(),
+	     format!("HPHP48-{}", romrev), romrev, width = RAW_WIDTH);
+
+    let end = match dump_object(&nibs, 0, 0) {
+	Ok(end) => end,
+	Err(e) => return crate::helpers::error_handler(format!("Error: {}", e)),
+    };
+
+    // The file is whole bytes, so one 0 nibble after an object with an
+    // odd length is expected.
+    let rest = &nibs[end..];
+    if rest.len() == 1 && rest[0] == 0 {
+	let _ = field(&nibs, end, 1, 0, "padding to a whole byte");
+    } else if !rest.is_empty() {
+	let _ = field(&nibs, end, rest.len(), 0, &format!("{} trailing nibbles after the object", rest.len()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hp_object::nibbles;
+
+    #[test]
+    fn finds_the_end_of_objects() {
+	// :: DUP ;
+	assert_eq!(dump_object(&nibbles("D9D20 88130 B2130"), 0, 0), Ok(15));
+	// :T: 1.5, inside a list
+	let list = nibbles("47A20 CFA20 10 45 33920 000 000000000051 0 B2130");
+	assert_eq!(dump_object(&list, 0, 0), Ok(list.len()));
+    }
+
+    #[test]
+    fn missing_semi_is_an_error() {
+	assert!(dump_object(&nibbles("D9D20 88130"), 0, 0).is_err());
+    }
+
+    #[test]
+    fn unknown_prolog_is_an_error() {
+	assert_eq!(dump_object(&nibbles("54321"), 0, 0).err(),
+		   Some("unknown prolog 12345 at nibble 00000".to_owned()));
+    }
+
+    #[test]
+    fn cut_off_field_is_an_error() {
+	// a BINT with 3 of its 5 value nibbles
+	assert!(dump_object(&nibbles("11920 A10"), 0, 0).is_err());
+    }
+}
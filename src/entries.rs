@@ -0,0 +1,51 @@
+// Names of ROM entry points, for showing System RPL. These are some
+// of the most common supported entries, at their HP 48 addresses.
+// There's no HP 49 table: read_object_file() refuses anything that
+// isn't an HPHP48 object, so a 49 program never gets here. Anything
+// else is shown as a PTR to its address, which is still what you'd
+// type into a SysRPL compiler.
+
+const ENTRIES: [(u32, &str); 32] = [
+    (0x0312b, "SEMI"),
+    (0x03188, "DUP"),
+    (0x031ac, "DUPDUP"),
+    (0x03223, "SWAP"),
+    (0x03244, "DROP"),
+    (0x03258, "2DROP"),
+    (0x03295, "ROT"),
+    (0x032c2, "OVER"),
+    (0x03a81, "TRUE"),
+    (0x03ac0, "FALSE"),
+    (0x03b2e, "EQ"),
+    (0x03b46, "EQUAL"),
+    (0x03dbc, "#+"),
+    (0x03de0, "#-"),
+    (0x03def, "#1+"),
+    (0x03e0e, "#1-"),
+    // built-in binary integers, 10 nibbles each
+    (0x03fef, "ZERO"),
+    (0x03ff9, "ONE"),
+    (0x04003, "TWO"),
+    (0x0400d, "THREE"),
+    (0x04017, "FOUR"),
+    (0x04021, "FIVE"),
+    (0x0402b, "SIX"),
+    (0x04035, "SEVEN"),
+    (0x0403f, "EIGHT"),
+    (0x04049, "NINE"),
+    (0x04053, "TEN"),
+    (0x04ed1, "ERRJMP"),
+    (0x055df, "NULL$"),
+    // built-in reals, 21 nibbles each
+    (0x2a2b4, "%0"),
+    (0x2a2c9, "%1"),
+    (0x2a2de, "%2"),
+];
+
+// Name of the entry point at `addr`, including the prologs.
+pub fn entry_name(addr: u32) -> Option<&'static str> {
+    if let Some(name) = crate::hp_object::prolog_name(addr) {
+	return Some(name);
+    }
+    return ENTRIES.iter().find(|(a, _)| *a == addr).map(|(_, name)| *name);
+}
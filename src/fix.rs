@@ -0,0 +1,253 @@
+// Check an object for damage and repair what we can. The object is
+// walked the same way calc_object_size() does, but copying it to a
+// new list of nibbles as we go, so that each repair happens where the
+// damage is:
+/*
+ * - the file ends early, which is what happens when XModem trims
+ *   0x00 bytes that were part of the object: pad with zero nibbles
+ * - a composite runs into the end of the file: add the missing SEMI,
+ *   or finish one that was cut off
+ * - the two length bytes of an ASCIX name don't match: use the first
+ * - junk after the object, like an extra 0x00 from Conn4x: drop it
+ */
+// A size field cut in half can't be repaired, because we don't know
+// how long the object was.
+
+use std::path::PathBuf;
+
+use console::style;
+
+use crate::hp_object;
+
+// 0x0312B, least significant nibble first
+const SEMI: [u8; 5] = [0xb, 0x2, 0x1, 0x3, 0x0];
+
+struct Repair<'a> {
+    nibs: &'a [u8],
+    out: Vec<u8>,
+    problems: Vec<String>,
+}
+
+impl Repair<'_> {
+    // Copy `count` nibbles starting at `pos`, padding with zeros if
+    // the file ends first. Returns the position after them.
+    fn copy(&mut self, pos: usize, count: usize, what: &str) -> usize {
+	let available = self.nibs.len().saturating_sub(pos).min(count);
+	self.out.extend_from_slice(&self.nibs[pos.min(self.nibs.len())..pos.min(self.nibs.len()) + available]);
+	if available < count {
+	    self.problems.push(format!("{} at nibble {:05X} is {} nibbles short; padded with zeros",
+				       what, pos, count - available));
+	    self.out.resize(self.out.len() + count - available, 0);
+	}
+	return pos + count;
+    }
+
+    // A field that has to be complete to go on.
+    fn field(&self, pos: usize, count: usize, what: &str) -> Result<u32, String> {
+	if pos + count > self.nibs.len() {
+	    return Err(format!("{} at nibble {:05X} is cut off by the end of the file; can't repair",
+			       what, pos));
+	}
+	return hp_object::read_nibbles(self.nibs, pos, count);
+    }
+
+    fn ascic(&mut self, pos: usize) -> Result<usize, String> {
+	let len = self.field(pos, 2, "name length")? as usize;
+	let pos = self.copy(pos, 2, "name length");
+	return Ok(self.copy(pos, len * 2, "name"));
+    }
+
+    fn ascix(&mut self, pos: usize) -> Result<usize, String> {
+	let len = self.field(pos, 2, "variable name length")? as usize;
+	let pos = self.copy(pos, 2 + len * 2, "variable name");
+	let trailing = self.field(pos, 2, "variable name length")? as usize;
+	if trailing != len {
+	    let name = hp_object::read_hp_string(self.nibs, pos - len * 2, len)?;
+	    self.problems.push(format!("ASCIX name {:?} at nibble {:05X} has lengths {} and {}; set both to {}",
+				       name, pos - len * 2 - 2, len, trailing, len));
+	}
+	self.out.push((len & 0xf) as u8);
+	self.out.push((len >> 4) as u8);
+	return Ok(pos + 2);
+    }
+
+    fn composite(&mut self, start: usize) -> Result<usize, String> {
+	let mut pos = start + 5;
+	loop {
+	    let rest = &self.nibs[pos.min(self.nibs.len())..];
+	    // nothing but the end of the file or zero padding left
+	    if rest.len() < 5 && rest.iter().all(|n| *n == 0) {
+		self.problems.push(format!("object at nibble {:05X} has no SEMI before the end of the file; added one",
+					   start));
+		self.out.extend_from_slice(&SEMI);
+		return Ok(self.nibs.len());
+	    }
+	    // the start of a SEMI whose last nibbles were trimmed, like
+	    // B213 when the 0x00 byte at the end of `:: DUP ;` is lost
+	    if rest.len() < 5 && SEMI.starts_with(rest) {
+		self.problems.push(format!("SEMI at nibble {:05X} is cut off by the end of the file; completed it",
+					   pos));
+		self.out.extend_from_slice(&SEMI);
+		return Ok(self.nibs.len());
+	    }
+	    let addr = self.field(pos, 5, "composite element")?;
+	    if addr == 0x312b {
+		return Ok(self.copy(pos, 5, "SEMI"));
+	    }
+	    pos = match hp_object::prolog_name(addr) {
+		Some(_) => self.object(pos)?,
+		None => self.copy(pos, 5, "pointer"),
+	    };
+	}
+    }
+
+    fn directory(&mut self, start: usize) -> Result<usize, String> {
+	let last_offset = self.field(start + 8, 5, "directory offset")? as usize;
+	let mut pos = self.copy(start + 5, 13, "directory header");
+	if last_offset == 0 {
+	    return Ok(pos);
+	}
+	loop {
+	    let name_start = pos;
+	    pos = self.ascix(pos)?;
+	    pos = self.object(pos)?;
+	    if name_start == start + 8 + last_offset {
+		return Ok(pos);
+	    } else if name_start > start + 8 + last_offset {
+		return Err("directory offset does not point to a variable name; can't repair".to_owned());
+	    }
+	    pos = self.copy(pos, 5, "directory link");
+	}
+    }
+
+    // Copy the object at `pos`, repairing it as we go. Returns the
+    // position after it in the original.
+    fn object(&mut self, pos: usize) -> Result<usize, String> {
+	let prolog = self.field(pos, 5, "prolog")?;
+	let name = match hp_object::prolog_name(prolog) {
+	    Some(n) => n,
+	    None => return Err(format!("unknown prolog {:05X} at nibble {:05X}; can't repair", prolog, pos)),
+	};
+	match prolog {
+	    0x2a74 | 0x2ab8 | 0x2ada | 0x2d9d => {
+		self.copy(pos, 5, "prolog");
+		self.composite(pos)
+	    },
+	    0x2a96 => {
+		self.copy(pos, 5, "prolog");
+		self.directory(pos)
+	    },
+	    0x2e48 | 0x2e6d => {
+		self.copy(pos, 5, "prolog");
+		self.ascic(pos + 5)
+	    },
+	    0x2afc => {
+		self.copy(pos, 5, "prolog");
+		let inner = self.ascic(pos + 5)?;
+		self.object(inner)
+	    },
+	    // fixed length objects
+	    0x2911 | 0x2933 | 0x2955 | 0x2977 | 0x299d | 0x29bf | 0x2e92 => {
+		let len = hp_object::prolog_to_fixed_length(prolog)? as usize;
+		Ok(self.copy(pos, len, name))
+	    },
+	    _ => {
+		let size = self.field(pos + 5, 5, &format!("size field of {}", name))? as usize;
+		if size < 5 {
+		    return Err(format!("size field at nibble {:05X} is less than its own length; can't repair", pos + 5));
+		}
+		Ok(self.copy(pos, 5 + size, name))
+	    },
+	}
+    }
+}
+
+// Check the object in `nibs` and return a repaired copy and the
+// problems that were fixed.
+pub fn repair(nibs: &[u8]) -> Result<(Vec<u8>, Vec<String>), String> {
+    let mut repair = Repair { nibs, out: Vec::new(), problems: Vec::new() };
+    let end = repair.object(0)?;
+
+    // one 0 nibble pads an odd-length object to whole bytes
+    let rest = &nibs[end.min(nibs.len())..];
+    let padding = if repair.out.len() % 2 == 1 { 1 } else { 0 };
+    if rest.len() > padding || rest.iter().any(|n| *n != 0) {
+	let junk = rest.len() - padding.min(rest.len());
+	repair.problems.push(format!("{} nibbles of trailing data after the object; removed", junk.max(1)));
+    }
+    return Ok((repair.out, repair.problems));
+}
+
+// Check the object at `path` and write a repaired copy to `output`
+// (default: next to the original). Returns the repaired file, or None
+// if nothing needed fixing.
+pub fn fix(path: &PathBuf, output: &Option<PathBuf>, overwrite: &bool) -> Option<PathBuf> {
+    let (romrev, nibs) = match hp_object::read_object_file(path) {
+	Ok(f) => f,
+	Err(e) => { crate::helpers::error_handler(format!("Error: {}", e)); return None; },
+    };
+    let (fixed, problems) = match repair(&nibs) {
+	Ok(r) => r,
+	Err(e) => { crate::helpers::error_handler(format!("Error: {}", e)); return None; },
+    };
+    if problems.is_empty() {
+	println!("{}", style("No problems found").green().bright());
+	return None;
+    }
+    for p in &problems {
+	println!("{}: {}", style("fixed").yellow().bright(), p);
+    }
+
+    let out_path = match output {
+	Some(p) => p.to_path_buf(),
+	None => path.to_path_buf(),
+    };
+    let final_path = match overwrite {
+	true => out_path,
+	false => crate::helpers::get_unique_path(out_path),
+    };
+    if let Err(e) = hp_object::write_object_file(&final_path, romrev, &fixed) {
+	crate::helpers::error_handler(format!("Error: {}", e));
+    }
+    println!("Wrote repaired object to {}", style(final_path.display()).yellow().bright());
+    return Some(final_path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // :: DUP ;
+    const DUP_PROGRAM: [u8; 15] = [0xd, 0x9, 0xd, 0x2, 0x0, 0x8, 0x8, 0x1, 0x3, 0x0, 0xb, 0x2, 0x1, 0x3, 0x0];
+
+    #[test]
+    fn good_object_needs_no_repair() {
+	let (fixed, problems) = repair(&DUP_PROGRAM).unwrap();
+	assert_eq!(fixed, DUP_PROGRAM);
+	assert!(problems.is_empty());
+    }
+
+    #[test]
+    fn completes_cut_off_semi() {
+	// the trailing 0x00 byte trimmed, leaving B213 at the end
+	let (fixed, problems) = repair(&DUP_PROGRAM[..14]).unwrap();
+	assert_eq!(fixed, DUP_PROGRAM);
+	assert_eq!(problems, vec!["SEMI at nibble 0000A is cut off by the end of the file; completed it"]);
+
+	let (fixed, _) = repair(&DUP_PROGRAM[..11]).unwrap();
+	assert_eq!(fixed, DUP_PROGRAM);
+    }
+
+    #[test]
+    fn adds_missing_semi() {
+	let (fixed, problems) = repair(&DUP_PROGRAM[..10]).unwrap();
+	assert_eq!(fixed, DUP_PROGRAM);
+	assert_eq!(problems.len(), 1);
+    }
+
+    #[test]
+    fn cut_off_pointer_is_not_a_semi() {
+	// 8813 is the start of DUP, not of SEMI
+	assert!(repair(&DUP_PROGRAM[..9]).is_err());
+    }
+}
@@ -0,0 +1,324 @@
+// Conversion between graphics objects (DOGROB, prolog 0x2b1e) and
+// PNG or PBM images. A GROB looks like this:
+/*
+ * prolog (5 nibbles)
+ * size of everything after the prolog (5 nibbles)
+ * height (5 nibbles)
+ * width (5 nibbles)
+ * bitmap, one row after another
+ */
+// Each row is padded to a whole number of bytes, so a 131-pixel wide
+// screen takes 34 nibbles per row. Within a nibble, the low bit is
+// the leftmost pixel, and a set bit is a dark pixel.
+
+use std::fs::File;
+use std::io::{BufWriter, Read};
+use std::path::PathBuf;
+
+use console::style;
+
+use crate::hp_object;
+
+pub struct Grob {
+    pub width: usize,
+    pub height: usize,
+    // one bool per pixel, row by row, true for dark pixels
+    pub pixels: Vec<bool>,
+}
+
+fn row_nibbles(width: usize) -> usize {
+    // round up to a whole byte
+    crate::helpers::div_up(width, 8) * 2
+}
+
+// The height, width and size are 5-nibble fields, so anything bigger
+// than they can hold can't be a GROB.
+fn check_size(width: usize, height: usize) -> Result<(), String> {
+    if width > 0xfffff || height > 0xfffff {
+	return Err(format!("a {}x{} image is too big for a GROB (at most {} pixels each way)",
+			   width, height, 0xfffff));
+    }
+    if 15 + row_nibbles(width) * height > 0xfffff {
+	return Err(format!("a {}x{} image is too big for a GROB (at most {} nibbles)", width, height, 0xfffff));
+    }
+    return Ok(());
+}
+
+pub fn read_grob(nibs: &[u8]) -> Result<Grob, String> {
+    if hp_object::get_prolog(nibs)? != 0x2b1e {
+	return Err("object is not a graphics object".to_owned());
+    }
+    let height = hp_object::read_nibbles(nibs, 10, 5)? as usize;
+    let width = hp_object::read_nibbles(nibs, 15, 5)? as usize;
+    let row_len = row_nibbles(width);
+    if 20 + row_len * height > nibs.len() {
+	return Err(format!("bitmap of {}x{} GROB is longer than the file", width, height));
+    }
+
+    let mut pixels: Vec<bool> = Vec::with_capacity(width * height);
+    for y in 0..height {
+	let row = &nibs[20 + y * row_len..20 + (y + 1) * row_len];
+	for x in 0..width {
+	    pixels.push(row[x / 4] & (1 << (x % 4)) != 0);
+	}
+    }
+    return Ok(Grob { width, height, pixels });
+}
+
+pub fn grob_to_nibbles(grob: &Grob) -> Result<Vec<u8>, String> {
+    check_size(grob.width, grob.height)?;
+    let row_len = row_nibbles(grob.width);
+    let mut body = vec![0u8; row_len * grob.height];
+    for y in 0..grob.height {
+	for x in 0..grob.width {
+	    if grob.pixels[y * grob.width + x] {
+		body[y * row_len + x / 4] |= 1 << (x % 4);
+	    }
+	}
+    }
+
+    let mut nibs: Vec<u8> = Vec::new();
+    for (value, count) in [(0x2b1e, 5), (15 + body.len(), 5), (grob.height, 5), (grob.width, 5)] {
+	for i in 0..count {
+	    nibs.push(((value >> (i * 4)) & 0xf) as u8);
+	}
+    }
+    nibs.extend_from_slice(&body);
+    return Ok(nibs);
+}
+
+fn write_png(grob: &Grob, path: &PathBuf) -> Result<(), String> {
+    let file = match File::create(path) {
+	Ok(f) => f,
+	Err(e) => return Err(format!("couldn't create {}: {}", path.display(), e)),
+    };
+    let mut encoder = png::Encoder::new(BufWriter::new(file), grob.width as u32, grob.height as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    // dark pixels are black, everything else is white
+    let data: Vec<u8> = grob.pixels.iter().map(|p| if *p { 0 } else { 255 }).collect();
+    let result = encoder.write_header().and_then(|mut writer| writer.write_image_data(&data));
+    match result {
+	Ok(_) => Ok(()),
+	Err(e) => Err(format!("couldn't write PNG: {}", e)),
+    }
+}
+
+fn read_png(path: &PathBuf) -> Result<Grob, String> {
+    let file = match File::open(path) {
+	Ok(f) => f,
+	Err(e) => return Err(format!("couldn't read {}: {}", path.display(), e)),
+    };
+    let mut decoder = png::Decoder::new(file);
+    // always get 8-bit gray or RGB, with or without alpha
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = match decoder.read_info() {
+	Ok(r) => r,
+	Err(e) => return Err(format!("couldn't decode PNG: {}", e)),
+    };
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = match reader.next_frame(&mut buf) {
+	Ok(f) => f,
+	Err(e) => return Err(format!("couldn't decode PNG: {}", e)),
+    };
+
+    let channels = frame.color_type.samples();
+    let mut pixels: Vec<bool> = Vec::new();
+    for px in buf[..frame.buffer_size()].chunks(channels) {
+	let (luma, alpha) = match frame.color_type {
+	    png::ColorType::Grayscale => (px[0] as u32, 255),
+	    png::ColorType::GrayscaleAlpha => (px[0] as u32, px[1]),
+	    png::ColorType::Rgb => ((px[0] as u32 * 299 + px[1] as u32 * 587 + px[2] as u32 * 114) / 1000, 255),
+	    png::ColorType::Rgba => ((px[0] as u32 * 299 + px[1] as u32 * 587 + px[2] as u32 * 114) / 1000, px[3]),
+	    // normalize_to_color8() expands palettes
+	    png::ColorType::Indexed => return Err("unexpected indexed PNG after decoding".to_owned()),
+	};
+	// Transparent pixels are background. Anything darker than
+	// mid-gray is a dark pixel on the calculator.
+	pixels.push(alpha >= 128 && luma < 128);
+    }
+    return Ok(Grob { width: frame.width as usize, height: frame.height as usize, pixels });
+}
+
+fn write_pbm(grob: &Grob, path: &PathBuf) -> Result<(), String> {
+    // raw (P4) PBM: rows padded to whole bytes, most significant bit
+    // first, 1 is black
+    let mut contents = format!("P4\n{} {}\n", grob.width, grob.height).into_bytes();
+    for y in 0..grob.height {
+	let mut byte = 0u8;
+	for x in 0..grob.width {
+	    if grob.pixels[y * grob.width + x] {
+		byte |= 0x80 >> (x % 8);
+	    }
+	    if x % 8 == 7 || x == grob.width - 1 {
+		contents.push(byte);
+		byte = 0;
+	    }
+	}
+    }
+    match std::fs::write(path, contents) {
+	Ok(_) => Ok(()),
+	Err(e) => Err(format!("couldn't write {}: {}", path.display(), e)),
+    }
+}
+
+fn read_pbm(path: &PathBuf) -> Result<Grob, String> {
+    let mut contents: Vec<u8> = Vec::new();
+    if let Err(e) = File::open(path).and_then(|mut f| f.read_to_end(&mut contents)) {
+	return Err(format!("couldn't read {}: {}", path.display(), e));
+    }
+
+    // The header is the magic number, width, and height separated by
+    // whitespace, with comments starting with #.
+    let mut index = 0usize;
+    let mut fields: Vec<String> = Vec::new();
+    while fields.len() < 3 && index < contents.len() {
+	let c = contents[index];
+	if c == b'#' {
+	    while index < contents.len() && contents[index] != b'\n' {
+		index += 1;
+	    }
+	} else if c.is_ascii_whitespace() {
+	    index += 1;
+	} else {
+	    let start = index;
+	    while index < contents.len() && !contents[index].is_ascii_whitespace() {
+		index += 1;
+	    }
+	    fields.push(String::from_utf8_lossy(&contents[start..index]).to_string());
+	}
+    }
+    if fields.len() < 3 {
+	return Err("PBM header is incomplete".to_owned());
+    }
+    let (width, height) = match (fields[1].parse::<usize>(), fields[2].parse::<usize>()) {
+	(Ok(w), Ok(h)) => (w, h),
+	_ => return Err("PBM width or height is not a number".to_owned()),
+    };
+    // before making room for the pixels
+    check_size(width, height)?;
+
+    let mut pixels: Vec<bool> = Vec::with_capacity(width * height);
+    match fields[0].as_str() {
+	"P1" => {
+	    // plain PBM: '0' and '1' characters, whitespace optional
+	    for c in &contents[index..] {
+		match c {
+		    b'0' => pixels.push(false),
+		    b'1' => pixels.push(true),
+		    _ => {},
+		}
+	    }
+	},
+	"P4" => {
+	    // exactly one whitespace character ends the header
+	    let data = match contents.get(index + 1..) {
+		Some(d) => d,
+		None => return Err("PBM file ends before its image data".to_owned()),
+	    };
+	    let row_len = crate::helpers::div_up(width, 8);
+	    for y in 0..height {
+		for x in 0..width {
+		    match data.get(y * row_len + x / 8) {
+			Some(byte) => pixels.push(byte & (0x80 >> (x % 8)) != 0),
+			None => return Err("PBM data is shorter than its header says".to_owned()),
+		    }
+		}
+	    }
+	},
+	_ => return Err("file is not a PBM image (must start with P1 or P4)".to_owned()),
+    }
+    if pixels.len() < width * height {
+	return Err("PBM data is shorter than its header says".to_owned());
+    }
+    pixels.truncate(width * height);
+    return Ok(Grob { width, height, pixels });
+}
+
+fn image_extension(path: &PathBuf) -> Result<String, String> {
+    match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()) {
+	Some(e) if e == "png" || e == "pbm" => Ok(e),
+	_ => Err(format!("{} must end in .png or .pbm", path.display())),
+    }
+}
+
+// Write the GROB at `path` to the image `output`, as PNG or PBM
+// depending on the extension of `output`.
+pub fn export(path: &PathBuf, output: &PathBuf, overwrite: &bool) {
+    let final_path = match overwrite {
+	true => output.to_path_buf(),
+	false => crate::helpers::get_unique_path(output.to_path_buf()),
+    };
+    let result = image_extension(output).and_then(|ext| {
+	let (_, nibs) = hp_object::read_object_file(path)?;
+	let grob = read_grob(&nibs)?;
+	match ext.as_str() {
+	    "png" => write_png(&grob, &final_path)?,
+	    _ => write_pbm(&grob, &final_path)?,
+	}
+	Ok(grob)
+    });
+    match result {
+	Ok(grob) => println!("Wrote {}x{} GROB to {}",
+			     grob.width, grob.height,
+			     style(final_path.display()).yellow().bright()),
+	Err(e) => crate::helpers::error_handler(format!("Error: {}", e)),
+    }
+}
+
+// Make a GROB object at `output` from the PNG or PBM image at `path`.
+pub fn import(path: &PathBuf, output: &PathBuf, overwrite: &bool) -> PathBuf {
+    let final_path = match overwrite {
+	true => output.to_path_buf(),
+	false => crate::helpers::get_unique_path(output.to_path_buf()),
+    };
+    let result = image_extension(path).and_then(|ext| {
+	let grob = match ext.as_str() {
+	    "png" => read_png(path)?,
+	    _ => read_pbm(path)?,
+	};
+	hp_object::write_object_file(&final_path, hp_object::DEFAULT_ROMREV, &grob_to_nibbles(&grob)?)?;
+	Ok(grob)
+    });
+    match result {
+	Ok(grob) => println!("Wrote {}x{} GROB to {}",
+			     grob.width, grob.height,
+			     style(final_path.display()).yellow().bright()),
+	Err(e) => crate::helpers::error_handler(format!("Error: {}", e)),
+    }
+    return final_path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_pixels() {
+	// 3x2, a diagonal and a dot
+	let grob = Grob { width: 3, height: 2, pixels: vec![true, false, false, false, true, true] };
+	let nibs = grob_to_nibbles(&grob).unwrap();
+	// each row is padded to a byte, so 2 nibbles
+	assert_eq!(nibs.len(), 20 + 2 * 2);
+	assert_eq!(&nibs[20..], [0b0001, 0, 0b0110, 0]);
+	let back = read_grob(&nibs).unwrap();
+	assert_eq!((back.width, back.height), (3, 2));
+	assert_eq!(back.pixels, grob.pixels);
+    }
+
+    #[test]
+    fn screen_rows_are_34_nibbles() {
+	assert_eq!(row_nibbles(131), 34);
+	assert_eq!(row_nibbles(8), 2);
+    }
+
+    #[test]
+    fn too_big_is_an_error() {
+	let wide = Grob { width: 0x100000, height: 0, pixels: Vec::new() };
+	assert!(grob_to_nibbles(&wide).is_err());
+	// fits the fields, but not the size
+	assert!(check_size(2048, 2048).is_err());
+	assert!(check_size(131, 64).is_ok());
+    }
+}
@@ -1,22 +1,53 @@
+use std::cell::RefCell;
 use std::path::PathBuf;
 use std::time::Duration;
 
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 
-pub fn get_file_contents(path: &PathBuf) -> Vec<u8> {
+// When several calculators are loaded at once (see deploy.rs), each
+// transfer runs in its own thread. Its progress bars go in a
+// MultiProgress with the port name in front, and errors end the
+// thread instead of the whole program.
+thread_local! {
+    static WORKER: RefCell<Option<(MultiProgress, String)>> = RefCell::new(None);
+}
+
+// What error_handler() panics with in a worker thread, so that
+// deploy.rs can tell it from a real panic, which should still be
+// printed.
+pub struct WorkerError(pub String);
+
+pub fn set_worker(bars: MultiProgress, label: String) {
+    WORKER.with(|w| *w.borrow_mut() = Some((bars, label)));
+}
+
+fn is_worker() -> bool {
+    return WORKER.with(|w| w.borrow().is_some());
+}
+
+// Add `pb` to this thread's MultiProgress, if it has one.
+fn attach(pb: ProgressBar) -> ProgressBar {
+    return WORKER.with(|w| match &*w.borrow() {
+	Some((bars, label)) => {
+	    pb.set_prefix(format!("{} ", label));
+	    bars.add(pb)
+	},
+	None => pb,
+    });
+}
+
+pub fn get_file_contents(path: &PathBuf) -> Result<Vec<u8>, String> {
     // This gives a Vec<u8>.
     // from https://www.reddit.com/r/rust/comments/dekpl5/comment/f2wminn/
-    let file_contents = match std::fs::read(path) {
-	// we have to make the match arms, well, match, so we return a
-	// new Vec, which should never actually get created
-	Err(e) => { error_handler(format!("couldn't read {}: {}", path.display(), e)); Vec::new() },
-	Ok(bytes) => bytes
-    };
-    return file_contents;
+    return std::fs::read(path).map_err(|e| format!("couldn't read {}: {}", path.display(), e));
 }
 
 // TODO: this should probably use colorized output, take a prefix argument, etc.
 pub fn error_handler(err: std::string::String) {
+    if is_worker() {
+	// deploy.rs gets this message when it joins the thread
+	std::panic::panic_any(WorkerError(err));
+    }
     eprintln!("{}", err);
     std::process::exit(1);
 }
@@ -34,10 +65,10 @@ pub fn get_progress_bar(len: u64) -> ProgressBar {
 		 
 		 // wide_bar means expand to fill space, :2 means
 		 // surround with 2 spaces (I think).
-		 .template(format!("{{wide_bar}} {{pos:>2}}/{{len:2}} packets ({{percent}}%)").as_str())
+		 .template(format!("{{prefix:.green}}{{wide_bar}} {{pos:>2}}/{{len:2}} packets ({{percent}}%)").as_str())
 		 .unwrap()
 		 .progress_chars("##-"));
-    return pb;
+    return attach(pb);
 }
 
 
@@ -45,7 +76,7 @@ pub fn get_spinner(label: std::string::String) -> ProgressBar {
     let pb = ProgressBar::new_spinner();
     pb.set_style(
         ProgressStyle::default_bar()
-	    .template("{spinner:} {msg}")
+	    .template("{prefix:.green}{spinner:} {msg}")
 	    .unwrap()
 	    // I like this spinner. It's reminiscent of systemd and very readable.
             .tick_strings(&[
@@ -68,6 +99,7 @@ pub fn get_spinner(label: std::string::String) -> ProgressBar {
             ]),
     );
     	
+    let pb = attach(pb);
     pb.set_message(label);
     pb.enable_steady_tick(Duration::from_millis(120)); // in ms
     /*
@@ -155,3 +187,42 @@ pub fn get_unique_path(path: PathBuf) -> PathBuf {
 	counter += 1;
     }
 }
+
+// The reverse of char_to_hp_char(): convert an HP 48 character to
+// Unicode. 0xa0 and up are Latin-1, so only the block between ASCII
+// and Latin-1 needs a table.
+pub fn hp_char_to_char(c: u8) -> char {
+    const HP_SPECIAL_CHARS: [char; 33] = [
+	'▒', '∡', 'x', '▽', '√', '∫', 'Σ', '▶', 'π', '∂', '≤', '≥', '≠', '𝛼', '→', '←', '↓',
+	'↑', 'γ', 'δ', 'ε', 'η', 'θ', 'λ', 'ρ', 'σ', 'τ', 'ω', 'Δ', 'Π', 'Ω', '■', '∞'];
+    match c {
+	0x00..=0x7e => c as char,
+	0x7f..=0x9f => HP_SPECIAL_CHARS[(c - 0x7f) as usize],
+	_ => char::from(c),
+    }
+}
+
+// `s` as a quoted JSON string. Rust's {:?} looks close, but it writes
+// control characters like \u{1b}, which isn't valid JSON.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+	match c {
+	    '"' => out.push_str("\\\""),
+	    '\\' => out.push_str("\\\\"),
+	    '\n' => out.push_str("\\n"),
+	    c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+	    c => out.push(c),
+	}
+    }
+    out.push('"');
+    return out;
+}
+
+// `s` as a CSV field, quoted if it has commas, quotes, or line breaks.
+pub fn csv_field(s: &str) -> String {
+    if s.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+	return format!("\"{}\"", s.replace('"', "\"\""));
+    }
+    return s.to_owned();
+}
@@ -3,6 +3,8 @@ use std::fmt;
 
 use console::style;
 
+use crate::helpers::{csv_field, json_string};
+
 //*** Code for converting a Vec of nibbles to text
 /*let mut ascix_text: Vec<char> = Vec::new();
 println!("ascix_char_len is {ascix_char_len}, ascix_region_len is {ascix_region_len}");
@@ -14,6 +16,10 @@ ascix_text.push(b as char);
     }
 println!("ascix_text is {:?}", ascix_text);*/
 
+// ROM revision written in the header of objects we make. The
+// calculator doesn't check this; R is the last 48GX revision.
+pub const DEFAULT_ROMREV: char = 'R';
+
 fn calc_crc(crc: &mut u32, nibble: u8) {
     *crc = (*crc >> 4) ^ (((*crc ^ nibble as u32) & 0xFu32) * 0x1081u32);
 }
@@ -27,9 +33,59 @@ enum LengthState {
 }
 
 pub struct ObjectInfo {
+    // file header, like HPHP48-R
+    pub header: String,
     pub romrev: char,
-    pub crc: std::string::String,
+    pub prolog: u32,
+    // length of the object in nibbles
     pub length: u32,
+    pub crc: u32,
+}
+
+impl ObjectInfo {
+    // BYTES reports size in bytes, including half bytes
+    pub fn bytes(&self) -> f32 {
+	self.length as f32 / 2.0
+    }
+
+    pub fn prolog_name(&self) -> &'static str {
+	prolog_name(self.prolog).unwrap_or("unknown")
+    }
+
+    pub fn object_type(&self) -> &'static str {
+	match object_type(self.prolog) {
+	    Some((_, name)) => name,
+	    None => "Unknown Object",
+	}
+    }
+
+    // TYPE number, if the calculator has one for this prolog.
+    fn type_number(&self) -> Option<u8> {
+	object_type(self.prolog).map(|(n, _)| n)
+    }
+
+    // Output as a JSON object, on one line.
+    pub fn to_json(&self) -> String {
+	format!("{{\"header\": {}, \"romrev\": {}, \"prolog\": {}, \"prolog_name\": {}, \
+		 \"type\": {}, \"type_name\": {}, \"nibbles\": {}, \"bytes\": {}, \"crc\": {}, \"crc_hex\": {}}}",
+		json_string(&self.header), json_string(&self.romrev.to_string()), self.prolog,
+		json_string(self.prolog_name()),
+		self.type_number().map_or("null".to_owned(), |n| n.to_string()), json_string(self.object_type()),
+		self.length, self.bytes(), self.crc, json_string(&format_crc(self.crc)))
+    }
+
+    pub const CSV_HEADER: &'static str = "header,romrev,prolog,prolog_name,type,type_name,nibbles,bytes,crc,crc_hex";
+
+    // Output as a CSV row in the order of CSV_HEADER, with the same
+    // values as the JSON (an unknown type is empty instead of null).
+    // The header and ROM revision come from the file, so they might
+    // need quotes.
+    pub fn to_csv(&self) -> String {
+	format!("{},{},{},{},{},{},{},{},{},{}",
+		csv_field(&self.header), csv_field(&self.romrev.to_string()), self.prolog, self.prolog_name(),
+		self.type_number().map_or(String::new(), |n| n.to_string()),
+		self.object_type(), self.length, self.bytes(), self.crc, format_crc(self.crc))
+    }
 }
 
 impl fmt::Display for ObjectInfo {
@@ -38,15 +94,24 @@ impl fmt::Display for ObjectInfo {
 	       style(self.romrev).green().bright(),
 	       // ROM revision is not part of BYTES, so why not make
 	       // it a separate color?
-	       style(&self.crc).blue().bright(),
-	       style(self.length as f32 / 2.0).blue().bright())
+	       style(format_crc(self.crc)).blue().bright(),
+	       style(self.bytes()).blue().bright())
     }
 }
+
+// Output format of the info command.
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum InfoFormat {
+    Text,
+    Json,
+    Csv,
+}
+
 // I am currently tempted to make this return a Result, but I don't think we need to.
 fn prolog_to_length(prolog: u32) -> Option<LengthState> {
     //println!("prolog is {:x?}", prolog);
     //        DOBINT  DOREAL  DOEREL  DOCMP   DOECMP  DOCHAR  DOROMP
-    for i in [0x2911, 0x2933, 0x2955, 0x2977, 0x299d, 0x29bf, 0x29e2] {
+    for i in [0x2911, 0x2933, 0x2955, 0x2977, 0x299d, 0x29bf, 0x2e92] {
 	if prolog == i {
 	    return Some(LengthState::Fixed);
 	}
@@ -94,7 +159,7 @@ fn prolog_to_length(prolog: u32) -> Option<LengthState> {
 
 
 // This does not need to have Option because prolog_to_length already checks for all these prologs.
-fn prolog_to_fixed_length(prolog: u32) -> Result<u32, String> {
+pub fn prolog_to_fixed_length(prolog: u32) -> Result<u32, String> {
     //println!("prolog to fixed length");
     match prolog {
 	// DOBINT
@@ -116,7 +181,7 @@ fn prolog_to_fixed_length(prolog: u32) -> Result<u32, String> {
     }
 }
 	    
-fn read_size(nibs: &Vec<u8>) -> Result<u32, String> {
+fn read_size(nibs: &[u8]) -> Result<u32, String> {
     // We have to go at least 10 nibbles in; if the object is less
     // than that, something is wrong.
     if nibs.len() < 10 {
@@ -133,7 +198,7 @@ fn read_size(nibs: &Vec<u8>) -> Result<u32, String> {
     return Ok(length + 5u32);
 }
 
-fn get_prolog(nibs: &Vec<u8>) -> Result<u32, String> {
+pub fn get_prolog(nibs: &[u8]) -> Result<u32, String> {
     if nibs.len() < 5 {
 	return Err("object is less than 5 nibbles long".to_owned());
     }
@@ -146,7 +211,174 @@ fn get_prolog(nibs: &Vec<u8>) -> Result<u32, String> {
     return Ok(prolog);
 }
 
-fn calc_object_size(nibs: &Vec<u8>) -> Result<u32, String> {
+// Read `count` nibbles starting at `start` as a little-endian
+// number, the way the Saturn reads a field from memory.
+pub fn read_nibbles(nibs: &[u8], start: usize, count: usize) -> Result<u32, String> {
+    if start + count > nibs.len() {
+	return Err(format!("field at nibble {} runs past the end of the object", start));
+    }
+    let mut value = 0u32;
+    for i in (start..start + count).rev() {
+	value <<= 4;
+	value |= nibs[i] as u32;
+    }
+    return Ok(value);
+}
+
+// The TYPE command's number and name for objects with prolog
+// `prolog`. Arrays are reported as real arrays, since telling them
+// apart needs the element type.
+pub fn object_type(prolog: u32) -> Option<(u8, &'static str)> {
+    match prolog {
+	0x2933 => Some((0, "Real Number")),
+	0x2977 => Some((1, "Complex Number")),
+	0x2a2c => Some((2, "String")),
+	0x29e8 => Some((3, "Array")),
+	0x2a74 => Some((5, "List")),
+	0x2e48 => Some((6, "Global Name")),
+	0x2e6d => Some((7, "Local Name")),
+	0x2d9d => Some((8, "Program")),
+	0x2ab8 => Some((9, "Algebraic")),
+	0x2a4e => Some((10, "Binary Integer")),
+	0x2b1e => Some((11, "Graphic")),
+	0x2afc => Some((12, "Tagged Object")),
+	0x2ada => Some((13, "Unit Object")),
+	0x2e92 => Some((14, "XLIB Name")),
+	0x2a96 => Some((15, "Directory")),
+	0x2b40 => Some((16, "Library")),
+	0x2b62 => Some((17, "Backup Object")),
+	0x2911 => Some((20, "System Binary")),
+	0x2955 => Some((21, "Extended Real")),
+	0x299d => Some((22, "Extended Complex")),
+	0x2a0a => Some((23, "Linked Array")),
+	0x29bf => Some((24, "Character")),
+	0x2dcc => Some((25, "Code Object")),
+	0x2b88 => Some((26, "Library Data")),
+	_ => None,
+    }
+}
+
+// Name of the object type with prolog `prolog`, as used in HP's
+// documentation. Returns None for prologs we don't know about.
+pub fn prolog_name(prolog: u32) -> Option<&'static str> {
+    match prolog {
+	0x2911 => Some("DOBINT"),
+	0x2933 => Some("DOREAL"),
+	0x2955 => Some("DOEREL"),
+	0x2977 => Some("DOCMP"),
+	0x299d => Some("DOECMP"),
+	0x29bf => Some("DOCHAR"),
+	0x29e8 => Some("DOARRY"),
+	0x2a0a => Some("DOLNKARRY"),
+	0x2a2c => Some("DOCSTR"),
+	0x2a4e => Some("DOHSTR"),
+	0x2a74 => Some("DOLIST"),
+	0x2a96 => Some("DORRP"),
+	0x2ab8 => Some("DOSYMB"),
+	0x2ada => Some("DOEXT"),
+	0x2afc => Some("DOTAG"),
+	0x2b1e => Some("DOGROB"),
+	0x2b40 => Some("DOLIB"),
+	0x2b62 => Some("DOBAK"),
+	0x2b88 => Some("DOEXT0"),
+	0x2d9d => Some("DOCOL"),
+	0x2dcc => Some("DOCODE"),
+	0x2e48 => Some("DOIDNT"),
+	0x2e6d => Some("DOLAM"),
+	0x2e92 => Some("DOROMP"),
+	_ => None,
+    }
+}
+
+// Decode `char_len` HP characters stored as byte pairs of nibbles,
+// starting at `start`.
+pub fn read_hp_string(nibs: &[u8], start: usize, char_len: usize) -> Result<String, String> {
+    let mut s = String::new();
+    for i in 0..char_len {
+	let c = read_nibbles(nibs, start + i * 2, 2)?;
+	s.push(crate::helpers::hp_char_to_char(c as u8));
+    }
+    return Ok(s);
+}
+
+// Read an ASCIX name (length byte, characters, length byte) at the
+// start of `nibs`. Returns the name and the nibbles it occupies.
+pub fn read_ascix_name(nibs: &[u8]) -> Result<(String, usize), String> {
+    let char_len = read_nibbles(nibs, 0, 2)? as usize;
+    let name = read_hp_string(nibs, 2, char_len)?;
+    let trailing_len = read_nibbles(nibs, 2 + char_len * 2, 2)? as usize;
+    if trailing_len != char_len {
+	return Err(format!("ASCIX name {:?} has mismatched lengths ({} and {})",
+			   name, char_len, trailing_len));
+    }
+    return Ok((name, 2 + char_len * 2 + 2));
+}
+
+// One variable stored in a directory. `start` and `len` locate the
+// object (not including its name) in the directory's nibbles.
+pub struct DirEntry {
+    pub name: String,
+    pub start: usize,
+    pub len: usize,
+}
+
+// A parsed directory object: the library attached to it (0x7ff if
+// none) and its variables in storage order.
+pub struct Directory {
+    pub attached_lib: u32,
+    pub entries: Vec<DirEntry>,
+    // total nibbles, including the prolog
+    pub len: usize,
+}
+
+// Walk the variables of the directory at the start of `nibs`. See
+// read_dir_size() for the layout. The 5-nibble field after the
+// attached library number is the offset from that field to the name
+// of the last variable, which is how we know when to stop without
+// relying on the end of the file.
+pub fn read_directory(nibs: &[u8]) -> Result<Directory, String> {
+    if get_prolog(nibs)? != 0x2a96 {
+	return Err("object is not a directory".to_owned());
+    }
+    let attached_lib = read_nibbles(nibs, 5, 3)?;
+    let last_offset = read_nibbles(nibs, 8, 5)? as usize;
+    let mut entries: Vec<DirEntry> = Vec::new();
+
+    if last_offset == 0 {
+	// empty directory: prolog, library, offset, and the 0x00000
+	// link that would come before the first name
+	return Ok(Directory { attached_lib, entries, len: 18 });
+    }
+    let last_name = 8 + last_offset;
+
+    // skip the link field before the first name
+    let mut index = 18usize;
+    loop {
+	let (name, name_len) = read_ascix_name(&nibs[index.min(nibs.len())..])?;
+	let start = index + name_len;
+	if start >= nibs.len() {
+	    return Err(format!("variable {:?} runs past the end of the directory", name));
+	}
+	let len = calc_object_size(&nibs[start..])? as usize;
+	if start + len > nibs.len() {
+	    return Err(format!("variable {:?} runs past the end of the directory", name));
+	}
+	let name_start = index;
+	entries.push(DirEntry { name, start, len });
+	index = start + len;
+
+	if name_start == last_name {
+	    break;
+	} else if name_start > last_name {
+	    return Err("directory offset does not point to a variable name".to_owned());
+	}
+	index += 5; // link to the previous variable
+    }
+
+    return Ok(Directory { attached_lib, entries, len: index });
+}
+
+pub fn calc_object_size(nibs: &[u8]) -> Result<u32, String> {
     let prolog = match get_prolog(&nibs) {
 	Ok(p) => p,
 	Err(e) => return Err(e),
@@ -162,40 +394,51 @@ fn calc_object_size(nibs: &Vec<u8>) -> Result<u32, String> {
 	
 	Ok(match object_length_type {
 	    Some(LengthState::SizeNext) => read_size(&nibs),
-	    Some(LengthState::ASCICNext) => read_ascic_size(&nibs),
-	    Some(LengthState::DirNext) => read_dir_size(&nibs),
+	    Some(LengthState::ASCICNext) => read_ascic_size(&nibs, prolog),
+	    // Use the directory's offset field to find its last
+	    // variable, so that a directory inside another one works,
+	    // and fall back to walking to the end of the data.
+	    Some(LengthState::DirNext) => match read_directory(&nibs) {
+		Ok(dir) => Ok(dir.len as u32),
+		Err(_) => read_dir_size(&nibs),
+	    },
 	    Some(LengthState::Fixed) => prolog_to_fixed_length(prolog),
-	    Some(LengthState::FindEndMarker) => read_size_to_end_marker(&nibs),
+	    Some(LengthState::FindEndMarker) => read_composite_size(&nibs),
 	    None => Err("unknown object prolog, could not calculate object length".to_owned()),
 	}?)
     }
 }
 
-fn read_ascic_size(nibs: &Vec<u8>) -> Result<u32, String> {
-    println!("read ascic size");
-    // ASCIC size is encoded as a byte (so up to 255 characters). We
-    // then need to go get more size, by reading the object that
-    // follows the ASCIC data.
-    let ascic_char_len = (nibs[1] << 4) + nibs[0];
-    let ascic_region_len = 2 + ascic_char_len * 2; // nibbles
-    // slice then reconvert to Vec
-    let inner_nibbles = nibs[ascic_region_len as usize..].to_vec();
+// Identifiers, local names, and tags have an ASCIC name after the
+// prolog: a length byte and then the characters. A tag is followed
+// by the object it tags.
+fn read_ascic_size(nibs: &[u8], prolog: u32) -> Result<u32, String> {
+    let char_len = read_nibbles(nibs, 5, 2)? as usize;
+    let name_end = 7 + char_len * 2;
+    if name_end > nibs.len() {
+	return Err("name runs past the end of the file".to_owned());
+    }
+    if prolog != 0x2afc {
+	return Ok(name_end as u32);
+    }
 
-    let inner_region_len = calc_object_size(&inner_nibbles);
-    match inner_region_len {
-	Ok(inner) => return Ok(inner + ascic_region_len as u32),
-	Err(e) => return Err("unable to read size of object in ASCIC field: ".to_owned() + &e),
+    match calc_object_size(&nibs[name_end..]) {
+	Ok(inner) => Ok(name_end as u32 + inner),
+	Err(e) => Err("unable to read size of tagged object: ".to_owned() + &e),
     }
 }
 
-fn read_ascix_size(nibs: &Vec<u8>) -> Result<u32, String> {
+fn read_ascix_size(nibs: &[u8]) -> Result<u32, String> {
     //println!("read_ascix_size, nibs is {:x?}, nibs.len() is {:?}", nibs, nibs.len());
     // ASCIX consists of <1 byte length, ASCII data, same 1 byte
     // length>. It's almost identical to ASCIC.
 
     
-    let ascix_char_len = (nibs[1] << 4) + nibs[0];
+    let ascix_char_len = read_nibbles(nibs, 0, 2)?;
     let ascix_region_len = 2 + (ascix_char_len*2) + 2;
+    if ascix_region_len as usize > nibs.len() {
+	return Err("variable name runs past the end of the file".to_owned());
+    }
 
     
     // slice then reconvert to Vec
@@ -204,45 +447,73 @@ fn read_ascix_size(nibs: &Vec<u8>) -> Result<u32, String> {
     let inner_region = calc_object_size(&inner_nibbles);
     match inner_region {
 	Ok(inner) => Ok(inner + ascix_region_len as u32),
-	Err(e) => return Err("unable to read size of object in ASCIC field: ".to_owned() + &e),
+	Err(e) => return Err("unable to read size of object after ASCIX name: ".to_owned() + &e),
     }
     //println!("inner_region is {:?} nibbles, {:?} bytes", inner_region.unwrap(), inner_region.unwrap() / 2);
 }
 
 
-fn read_size_to_end_marker(nibs: &Vec<u8>) -> Result<u32, String> {
-    //println!("read_size_to_end_marker, nibs is {:x?}", nibs);
-    let mut mem_addr = 0u32; // address in Saturn memory, 5 nibbles
-    for (pos, i) in nibs.iter().enumerate() {
-	mem_addr <<= 4;
-	mem_addr |= *i as u32;
-	mem_addr &= 0xfffffu32; // Saturn uses 20-bit address
-	//println!("{:?}: {:#x}", pos, mem_addr);
-	
-	// object end marker, reversed (SEMI is actually 0x312b)
-	// because the calculator reads nibbles in reverse
-	
-	// note that end marker is just SEMI---so a program could
-	// contain multiple secondaries, and we have to pick up
-	// only the very last SEMI. the `pos == ...` term does
-	// that.
-
-	// Also, because the HP pads to the nearest byte, there could
-	// actually be another 0 nibble after 'b2130', hence `...len()
-	// - 2`.
-	if mem_addr == 0xb2130 && (pos == nibs.len() - 1 || pos == nibs.len() - 2) {
-	    //println!("found end marker, exiting");
-	    
-	    // add 1 to convert index to length.
-	    return Ok(pos as u32 + 1);
+// Walk the elements of a composite object (program, list, algebraic,
+// unit) until the SEMI that closes it. Each element is either an
+// object with a prolog we know, or a 5-nibble pointer to something in
+// ROM (a command like + or DUP). Returns the start and length of each
+// element, not including the SEMI.
+pub fn composite_elements(nibs: &[u8]) -> Result<Vec<(usize, usize)>, String> {
+    let mut elements: Vec<(usize, usize)> = Vec::new();
+    let mut index = 5usize;
+    loop {
+	let addr = read_nibbles(nibs, index, 5)?;
+	if addr == 0x312b {
+	    return Ok(elements);
+	}
+	let len = match prolog_to_length(addr) {
+	    Some(_) => calc_object_size(&nibs[index..])? as usize,
+	    None => 5,
+	};
+	elements.push((index, len));
+	index += len;
+    }
+}
+
+// Find the end of a composite (program, list, algebraic, unit) by
+// walking its elements. Composites nest, so every composite prolog we
+// meet goes on a stack and every SEMI closes the innermost one; the
+// object ends at the SEMI that empties the stack. Other objects are
+// skipped by their own size, so a SEMI-like pattern inside a string
+// or code object doesn't end anything, and neither does padding or
+// junk after the object. This is what lets us find the end of a
+// program stored inside a directory, where the SEMI isn't at the end
+// of the data.
+fn read_composite_size(nibs: &[u8]) -> Result<u32, String> {
+    let mut stack: Vec<usize> = vec![0];
+    let mut index = 5usize;
+    while !stack.is_empty() {
+	if index + 5 > nibs.len() {
+	    return Err(format!("no end marker (0x0312B) found for composite at nibble {}",
+			       stack.last().unwrap()));
+	}
+	let addr = read_nibbles(nibs, index, 5)?;
+	if addr == 0x312b {
+	    stack.pop();
+	    index += 5;
+	    continue;
+	}
+	match prolog_to_length(addr) {
+	    Some(LengthState::FindEndMarker) => {
+		stack.push(index);
+		index += 5;
+	    },
+	    Some(_) => index += calc_object_size(&nibs[index..])? as usize,
+	    // a pointer to something in ROM, like + or DUP
+	    None => index += 5,
 	}
     }
-    return Err("no end marker (0x0312B) found".to_owned());
+    return Ok(index as u32);
 }
 
 
 // This is a function for a specific type of variable, so 
-fn read_dir_size(nibs: &Vec<u8>) -> Result<u32, String> {
+fn read_dir_size(nibs: &[u8]) -> Result<u32, String> {
     //println!("read_dir_size");
     // A directory consists of the prolog (5 nibbles), attached
     // libraries (3 nibbles), an offset number (5 nibbles), and
@@ -292,16 +563,54 @@ fn read_dir_size(nibs: &Vec<u8>) -> Result<u32, String> {
 // then uses that value to iterate over the appropriate portion of the
 // file, calculating the CRC on each nibble.
 
-fn crc_file(path: &PathBuf) -> Result<ObjectInfo, String> {
-    // can't use ? operator here because the function returns ObjectInfo
+pub fn crc_file(path: &PathBuf) -> Result<ObjectInfo, String> {
+    let (romrev, nibbles) = read_object_file(path)?;
+    return object_info(romrev, &nibbles);
+}
+
+// The part of crc_file() that works on the nibbles of an object
+// already in memory, like one received in `alum shell`.
+pub fn object_info(romrev: char, nibbles: &[u8]) -> Result<ObjectInfo, String> {
+    let object_length = calc_object_size(&nibbles)?;
+    
+    //println!("object_length is {:?}", object_length);
+    // The HP 48 will expand a file send to the computer into bytes,
+    // so an object that is an odd number of nibbles (like a real,
+    // which is 21 nibbles), will be expanded to 22 nibbles on the
+    // computer. Therefore, the calculating program must act depending
+    // on the prolog of the object.
+
+    // We have the actual number of nibbles the object occupies in
+    // object_length, so we can iterate from the start to that many
+    // nibbles.
+    if (object_length as usize) > nibbles.len() {
+	return Err("object length is greater than file size; file may be corrupt".to_owned());
+    }
+    let crc = crc_nibbles(&nibbles[0..object_length as usize]);
+
+    return Ok(ObjectInfo {
+	header: format!("HPHP48-{}", romrev),
+	romrev: romrev,
+	prolog: get_prolog(&nibbles)?,
+	length: object_length,
+	crc: crc,
+    });
+}
+
+// Read the HP 48 binary at `path`, check its header, and split the
+// body into nibbles. Returns the ROM revision character and the
+// nibbles, low nibble of each byte first.
+pub fn read_object_file(path: &PathBuf) -> Result<(char, Vec<u8>), String> {
     let file_contents = match std::fs::read(path) {
-	Err(e) => {
-	    crate::helpers::error_handler(format!("Error: couldn't read file: {:?}", e));
-	    Vec::new()
-	},
+	Err(e) => return Err(format!("couldn't read {}: {}", path.display(), e)),
 	Ok(bytes) => bytes,
     };
+    return bytes_to_nibbles(&file_contents);
+}
 
+// The part of read_object_file() that works on the contents of a file
+// in memory.
+pub fn bytes_to_nibbles(file_contents: &[u8]) -> Result<(char, Vec<u8>), String> {
     // shortest possible object is a char at 7 nibbles; 7 nibbles plus 8 bytes = 12 bytes rounded up.
     if file_contents.len() < 12 {
 	return Err("file is corrupt (too short to be an HP object)".to_owned());
@@ -315,7 +624,7 @@ fn crc_file(path: &PathBuf) -> Result<ObjectInfo, String> {
 	return Err("file is not an HP 48 binary object (does not start with HPHP48)".to_owned());
     }
 
-    let romrev = *&file_contents[7] as char;
+    let romrev = file_contents[7] as char;
 
     // split file_contents into bytes each containing one nibble
     let mut nibbles: Vec<u8> = Vec::new();
@@ -323,56 +632,77 @@ fn crc_file(path: &PathBuf) -> Result<ObjectInfo, String> {
 	nibbles.push(byte & 0xfu8); // low nibble
 	nibbles.push(byte >> 4); // high nibble
     }
+    return Ok((romrev, nibbles));
+}
 
-    let prolog = match get_prolog(&nibbles) {
-	Ok(pro) => pro,
-	Err(e) => return Err(e.to_string()),
-    };
-
-    let object_length = match prolog_to_length(prolog) {
-	Some(LengthState::SizeNext) => read_size(&nibbles),
-	Some(LengthState::ASCICNext) => read_ascic_size(&nibbles),
-	Some(LengthState::DirNext) => read_dir_size(&nibbles),
-	Some(LengthState::Fixed) => prolog_to_fixed_length(prolog),
-	Some(LengthState::FindEndMarker) => read_size_to_end_marker(&nibbles),
-	None => return Err("unknown object prolog, could not calculate object length".to_owned()),
-    }?;
-
-    
-    //println!("prolog is {:?}", prolog_to_length(prolog));
-    //println!("object_length is {:?}", object_length);
-    // The HP 48 will expand a file send to the computer into bytes,
-    // so an object that is an odd number of nibbles (like a real,
-    // which is 21 nibbles), will be expanded to 22 nibbles on the
-    // computer. Therefore, the calculating program must act depending
-    // on the prolog of the object.
+// The reverse of read_object_file(): write `nibs` to `path` behind an
+// HPHP48 header, padding the final byte with a 0 nibble if needed.
+pub fn write_object_file(path: &PathBuf, romrev: char, nibs: &[u8]) -> Result<(), String> {
+    let mut contents: Vec<u8> = b"HPHP48-".to_vec();
+    contents.push(romrev as u8);
+    for pair in nibs.chunks(2) {
+	let high = match pair.get(1) {
+	    Some(n) => *n,
+	    None => 0,
+	};
+	contents.push((high << 4) | pair[0]);
+    }
+    match std::fs::write(path, contents) {
+	Ok(_) => Ok(()),
+	Err(e) => Err(format!("couldn't write {}: {}", path.display(), e)),
+    }
+}
 
-    // We have the actual number of nibbles the object occupies in
-    // object_length, so we can iterate from the start to that many
-    // nibbles.
+// Run the HP CRC over a list of nibbles.
+pub fn crc_nibbles(nibs: &[u8]) -> u32 {
     let mut crc = 0u32;
-    //println!("nibble length is {:?}, nibs.len() is {:?}", object_length.unwrap(), nibbles.len());
-    //println!("nibbles is {:x?}, nibs.len() is {:?}", nibbles, nibbles.len());
-    if (object_length as usize) > nibbles.len() {
-	return Err("object length is greater than file size; file may be corrupt".to_owned());
-    }
-    for nibble in &nibbles[0..object_length as usize] {
-	//println!("nibble is {:x?}", *nibble);
-	
+    for nibble in nibs {
 	// A CRC calculation sets the value of the crc variable based
 	// on its previous value, therefore, we can use a mut
 	// reference.
 	calc_crc(&mut crc, *nibble);
     }
+    return crc;
+}
 
+// Format a CRC the way the calculator's BYTES command shows it.
+pub fn format_crc(crc: u32) -> String {
     // HP hex strings are uppercase
-    let initial_str = format!("{:#x}", crc).to_uppercase();
+    return format!("#{:X}h", crc);
+}
 
-    return Ok(ObjectInfo {
-	romrev: romrev,
-	crc: format!("#{}h", &initial_str[2..]),
-	length: object_length,
-    });
+// For `info --strict`: the file should end with the object, plus one
+// 0 nibble of padding if the object has an odd length.
+pub fn check_trailing(path: &PathBuf, info: &ObjectInfo) -> Result<(), String> {
+    let (_, nibbles) = read_object_file(path)?;
+    let rest = &nibbles[info.length as usize..];
+    if rest.len() > (info.length % 2) as usize || rest.iter().any(|n| *n != 0) {
+	return Err(format!("{} nibbles of trailing data after the object (alum fix can remove them)",
+			   rest.len() - (info.length % 2) as usize));
+    }
+    return Ok(());
+}
+
+// Print info about `path` in `format`. The text format is the same
+// as crc_and_output(). `strict` makes trailing data an error.
+pub fn info_and_output(path: &PathBuf, format: &InfoFormat, strict: &bool) {
+    let info = match crc_file(path) {
+	Ok(i) => i,
+	Err(e) => return crate::helpers::error_handler(format!("Error: {}", e)),
+    };
+    if *strict {
+	if let Err(e) = check_trailing(path, &info) {
+	    return crate::helpers::error_handler(format!("Error: {}", e));
+	}
+    }
+    match format {
+	InfoFormat::Text => println!("{}", info),
+	InfoFormat::Json => println!("{}", info.to_json()),
+	InfoFormat::Csv => {
+	    println!("{}", ObjectInfo::CSV_HEADER);
+	    println!("{}", info.to_csv());
+	},
+    }
 }
 
 // crate::helpers::error_handler(format!("{}: {}", style("Error").red(), e)),
@@ -383,3 +713,66 @@ pub fn crc_and_output(path: &PathBuf) {
 	Err(e) => crate::helpers::error_handler(format!("Error: {}", e)),
     }
 }
+
+// Nibbles in the order they're stored, so prolog 0x02A74 is "47A20".
+// Spaces are ignored. For writing objects out in tests.
+#[cfg(test)]
+pub fn nibbles(s: &str) -> Vec<u8> {
+    s.chars().filter(|c| !c.is_whitespace()).map(|c| c.to_digit(16).unwrap() as u8).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_quotes_fields_from_the_file() {
+	let info = ObjectInfo { header: "HPHP48-,".to_owned(), romrev: ',', prolog: 0x2a2c, length: 10, crc: 0x1234 };
+	let row = info.to_csv();
+	assert!(row.starts_with("\"HPHP48-,\",\",\",10796,"), "{}", row);
+	assert_eq!(row.matches(',').count() - 2, ObjectInfo::CSV_HEADER.matches(',').count());
+    }
+
+    #[test]
+    fn identifier_size() {
+	// ID A
+	assert_eq!(read_ascic_size(&nibbles("84E20 10 14"), 0x2e48), Ok(9));
+	assert!(read_ascic_size(&nibbles("84E20 20 14"), 0x2e48).is_err());
+    }
+
+    #[test]
+    fn odd_length_object_with_padding() {
+	// ID A is 9 nibbles, so the file pads it with a 0 nibble, which
+	// isn't part of the object
+	let nibs = nibbles("84E20 10 14 0");
+	assert_eq!(calc_object_size(&nibs), Ok(9));
+	assert_eq!(read_ascic_size(&nibs, 0x2e48), Ok(9));
+    }
+
+    #[test]
+    fn tagged_size() {
+	// :T: # 5h
+	let nibs = nibbles("CFA20 10 45 11920 50000");
+	assert_eq!(read_ascic_size(&nibs, 0x2afc), Ok(19));
+	assert_eq!(calc_object_size(&nibs), Ok(19));
+	// the file ends in the tagged object's prolog
+	assert!(read_ascic_size(&nibs[..11], 0x2afc).is_err());
+    }
+
+    #[test]
+    fn nested_list_size() {
+	// { { } DUP }
+	let nibs = nibbles("47A20 47A20 B2130 88130 B2130");
+	assert_eq!(read_composite_size(&nibs), Ok(25));
+	// missing the outer SEMI
+	assert!(read_composite_size(&nibs[..20]).is_err());
+    }
+
+    #[test]
+    fn unit_size() {
+	// 1_m: a real, a string, and a ROM pointer, then SEMI
+	let nibs = nibbles("ADA20 33920 000 000000000001 0 C2A20 70000 D6 E5B01 B2130");
+	assert_eq!(read_composite_size(&nibs), Ok(48));
+	assert_eq!(calc_object_size(&nibs), Ok(48));
+    }
+}
@@ -0,0 +1,439 @@
+// Decoding and encoding of real and complex numbers. A real number
+// (DOREAL, prolog 0x2933) is stored in BCD:
+/*
+ * prolog (5 nibbles)
+ * exponent, 10's complement (3 nibbles)
+ * mantissa, one digit per nibble, with an implied decimal point
+ *   after the first digit (12 nibbles)
+ * sign, 0 for positive and 9 for negative (1 nibble)
+ */
+// Everything is stored least significant nibble first, like any other
+// field. An extended real (DOEREL) has a 5-nibble exponent and 15
+// mantissa digits. A complex number (DOCMP or DOECMP) is two reals
+// (or extended reals) without prologs, real part first.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use console::style;
+
+use crate::hp_object;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HpReal {
+    pub negative: bool,
+    // mantissa digits as an integer, so 1.5 is 150000000000
+    pub mantissa: u64,
+    pub exponent: i32,
+    // number of mantissa digits, 12 or 15 for extended reals
+    pub digits: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HpNumber {
+    Real(HpReal),
+    Complex(HpReal, HpReal),
+}
+
+impl HpReal {
+    pub fn zero(digits: usize) -> HpReal {
+	HpReal { negative: false, mantissa: 0, exponent: 0, digits }
+    }
+
+    fn exponent_nibbles(&self) -> usize {
+	match self.digits {
+	    15 => 5,
+	    _ => 3,
+	}
+    }
+
+    // Decode a real from `nibs`, which starts after the prolog.
+    pub fn from_nibbles(nibs: &[u8], digits: usize) -> Result<HpReal, String> {
+	let exp_len = match digits {
+	    15 => 5,
+	    _ => 3,
+	};
+	if nibs.len() < exp_len + digits + 1 {
+	    return Err("real number is cut short".to_owned());
+	}
+	for n in &nibs[0..exp_len + digits] {
+	    if *n > 9 {
+		return Err("real number contains a digit that isn't BCD".to_owned());
+	    }
+	}
+
+	let mut exponent = 0i32;
+	for n in nibs[0..exp_len].iter().rev() {
+	    exponent = match exponent.checked_mul(10).and_then(|e| e.checked_add(*n as i32)) {
+		Some(e) => e,
+		None => return Err("real number has too many exponent digits".to_owned()),
+	    };
+	}
+	// 10's complement, so 999 is -1
+	let modulus = 10i32.pow(exp_len as u32);
+	if exponent >= modulus / 2 {
+	    exponent -= modulus;
+	}
+
+	let mut mantissa = 0u64;
+	for n in nibs[exp_len..exp_len + digits].iter().rev() {
+	    mantissa = mantissa * 10 + *n as u64;
+	}
+
+	let negative = match nibs[exp_len + digits] {
+	    0 => false,
+	    9 => true,
+	    n => return Err(format!("real number has invalid sign nibble {:x}", n)),
+	};
+	return Ok(HpReal { negative, mantissa, exponent, digits });
+    }
+
+    // The reverse of from_nibbles(), without a prolog.
+    pub fn to_nibbles(&self) -> Vec<u8> {
+	let mut nibs: Vec<u8> = Vec::new();
+	let exp_len = self.exponent_nibbles();
+	let modulus = 10i64.pow(exp_len as u32);
+	let mut exponent = (self.exponent as i64).rem_euclid(modulus);
+	for _ in 0..exp_len {
+	    nibs.push((exponent % 10) as u8);
+	    exponent /= 10;
+	}
+	let mut mantissa = self.mantissa;
+	for _ in 0..self.digits {
+	    nibs.push((mantissa % 10) as u8);
+	    mantissa /= 10;
+	}
+	nibs.push(if self.negative { 9 } else { 0 });
+	return nibs;
+    }
+
+    // Parse a decimal number like "-1.5", "2.", ".25", or "6.02E23",
+    // rounding to `digits` significant digits the way the calculator
+    // does when you type a longer number.
+    pub fn parse(s: &str, digits: usize) -> Result<HpReal, String> {
+	let s = s.trim();
+	let (negative, rest) = match s.strip_prefix('-') {
+	    Some(r) => (true, r),
+	    None => (false, s.strip_prefix('+').unwrap_or(s)),
+	};
+	let (number, exp_str) = match rest.find(|c| c == 'E' || c == 'e') {
+	    Some(pos) => (&rest[..pos], Some(&rest[pos + 1..])),
+	    None => (rest, None),
+	};
+	// Collect the significant digits and move the exponent so that
+	// the decimal point is after the first one.
+	let mut sig: Vec<u8> = Vec::new();
+	let mut seen_point = false;
+	let mut int_digits = 0i32;
+	let mut leading_frac_zeros = 0i32;
+	for c in number.chars() {
+	    match c {
+		'.' if !seen_point => seen_point = true,
+		'0'..='9' => {
+		    let d = c as u8 - b'0';
+		    if sig.is_empty() && d == 0 {
+			if seen_point {
+			    leading_frac_zeros += 1;
+			}
+			continue;
+		    }
+		    if !seen_point {
+			int_digits += 1;
+		    }
+		    sig.push(d);
+		},
+		_ => return Err(format!("{:?} is not a decimal number", s)),
+	    }
+	}
+	if !number.chars().any(|c| c.is_ascii_digit()) {
+	    return Err(format!("{:?} is not a decimal number", s));
+	}
+	let out_of_range = format!("{:?} is out of range for a{} real number", s,
+				   if digits == 15 { "n extended" } else { "" });
+	let exponent: i32 = match exp_str {
+	    Some(e) => match e.parse::<i32>() {
+		Ok(v) => v,
+		Err(_) => return Err(format!("{:?} has an invalid exponent", s)),
+	    },
+	    None => 0,
+	};
+	if sig.is_empty() {
+	    return Ok(HpReal::zero(digits));
+	}
+	// an exponent near i32::MAX would overflow here, and is out of
+	// range anyway
+	let shift = match int_digits {
+	    0 => -leading_frac_zeros - 1,
+	    n => n - 1,
+	};
+	let mut exponent = match exponent.checked_add(shift) {
+	    Some(e) => e,
+	    None => return Err(out_of_range),
+	};
+
+	// round half up to `digits` digits
+	let round_up = sig.len() > digits && sig[digits] >= 5;
+	sig.resize(digits, 0);
+	let mut mantissa = 0u64;
+	for d in &sig {
+	    mantissa = mantissa * 10 + *d as u64;
+	}
+	if round_up {
+	    mantissa += 1;
+	    if mantissa == 10u64.pow(digits as u32) {
+		// 9.99... rounded up to 10
+		mantissa /= 10;
+		exponent = match exponent.checked_add(1) {
+		    Some(e) => e,
+		    None => return Err(out_of_range),
+		};
+	    }
+	}
+
+	let limit = match digits {
+	    15 => 50000,
+	    _ => 500,
+	};
+	if exponent >= limit || exponent <= -limit {
+	    return Err(out_of_range);
+	}
+	return Ok(HpReal { negative, mantissa, exponent, digits });
+    }
+
+    // Format for other programs: like STD, but always a valid JSON
+    // number, so .25 is 0.25 and 1.E12 is 1E12.
+    pub fn to_decimal_string(&self) -> String {
+	let s = self.to_string().replace(".E", "E");
+	if let Some(rest) = s.strip_prefix("-.") {
+	    return format!("-0.{}", rest);
+	} else if let Some(rest) = s.strip_prefix('.') {
+	    return format!("0.{}", rest);
+	}
+	return s;
+    }
+
+    // The mantissa and exponent with every digit, like
+    // 1.50000000000E0, so that nothing is lost to formatting.
+    pub fn to_exact_string(&self) -> String {
+	let m = format!("{:0width$}", self.mantissa, width = self.digits);
+	format!("{}{}.{}E{}", if self.negative { "-" } else { "" }, &m[0..1], &m[1..], self.exponent)
+    }
+}
+
+// Format the way the HP 48 does in STD mode: as many digits as
+// needed, no leading zero, and scientific notation only when the
+// number can't be shown with all its digits otherwise. Whole numbers
+// have no decimal point (2, not the HP 49's 2.), but a mantissa with
+// one digit keeps it, like 1.E12.
+impl fmt::Display for HpReal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+	if self.mantissa == 0 {
+	    return write!(f, "0");
+	}
+	let m = format!("{:0width$}", self.mantissa, width = self.digits);
+	let sig = m.trim_end_matches('0');
+	let n = sig.len() as i32;
+	let exp = self.exponent;
+	let digits = self.digits as i32;
+
+	let s = if exp >= 0 && exp < digits {
+	    let int_len = (exp + 1) as usize;
+	    if sig.len() <= int_len {
+		format!("{}{}", sig, "0".repeat(int_len - sig.len()))
+	    } else {
+		format!("{}.{}", &sig[..int_len], &sig[int_len..])
+	    }
+	} else if exp < 0 && -exp - 1 + n <= digits {
+	    format!(".{}{}", "0".repeat((-exp - 1) as usize), sig)
+	} else {
+	    match sig.len() {
+		1 => format!("{}.E{}", sig, exp),
+		_ => format!("{}.{}E{}", &sig[0..1], &sig[1..], exp),
+	    }
+	};
+	write!(f, "{}{}", if self.negative { "-" } else { "" }, s)
+    }
+}
+
+impl fmt::Display for HpNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+	match self {
+	    HpNumber::Real(r) => write!(f, "{}", r),
+	    HpNumber::Complex(re, im) => write!(f, "({},{})", re, im),
+	}
+    }
+}
+
+impl HpNumber {
+    pub fn to_exact_string(&self) -> String {
+	match self {
+	    HpNumber::Real(r) => r.to_exact_string(),
+	    HpNumber::Complex(re, im) => format!("({},{})", re.to_exact_string(), im.to_exact_string()),
+	}
+    }
+
+    // Parse a real like "1.5" or a complex number like "(1.5,-2)".
+    // `extended` makes extended reals and complexes.
+    pub fn parse(s: &str, extended: bool) -> Result<HpNumber, String> {
+	let digits = if extended { 15 } else { 12 };
+	let s = s.trim();
+	if let Some(inner) = s.strip_prefix('(').and_then(|i| i.strip_suffix(')')) {
+	    let parts: Vec<&str> = inner.split(',').collect();
+	    if parts.len() != 2 {
+		return Err(format!("{:?} is not a complex number like (1.5,2)", s));
+	    }
+	    return Ok(HpNumber::Complex(HpReal::parse(parts[0], digits)?, HpReal::parse(parts[1], digits)?));
+	}
+	return Ok(HpNumber::Real(HpReal::parse(s, digits)?));
+    }
+
+    // Length of the body of a number with `prolog`, as stored without
+    // the prolog in an array.
+    pub fn body_len(prolog: u32) -> Option<usize> {
+	match prolog {
+	    0x2933 => Some(16),
+	    0x2955 => Some(21),
+	    0x2977 => Some(32),
+	    0x299d => Some(42),
+	    _ => None,
+	}
+    }
+
+    // Decode a number stored without a prolog, as in an array whose
+    // elements have prolog `prolog`.
+    pub fn from_body(nibs: &[u8], prolog: u32) -> Result<HpNumber, String> {
+	match prolog {
+	    0x2933 => Ok(HpNumber::Real(HpReal::from_nibbles(nibs, 12)?)),
+	    0x2955 => Ok(HpNumber::Real(HpReal::from_nibbles(nibs, 15)?)),
+	    0x2977 => Ok(HpNumber::Complex(HpReal::from_nibbles(nibs, 12)?,
+					   HpReal::from_nibbles(&nibs[16..], 12)?)),
+	    0x299d => Ok(HpNumber::Complex(HpReal::from_nibbles(nibs, 15)?,
+					   HpReal::from_nibbles(&nibs[21..], 15)?)),
+	    _ => Err("object is not a real or complex number".to_owned()),
+	}
+    }
+
+    pub fn prolog(&self) -> u32 {
+	match self {
+	    HpNumber::Real(r) if r.digits == 15 => 0x2955,
+	    HpNumber::Real(_) => 0x2933,
+	    HpNumber::Complex(re, _) if re.digits == 15 => 0x299d,
+	    HpNumber::Complex(_, _) => 0x2977,
+	}
+    }
+
+    // Body without the prolog.
+    pub fn body_nibbles(&self) -> Vec<u8> {
+	match self {
+	    HpNumber::Real(r) => r.to_nibbles(),
+	    HpNumber::Complex(re, im) => {
+		let mut body = re.to_nibbles();
+		body.extend(im.to_nibbles());
+		body
+	    },
+	}
+    }
+
+    // Complete object, including the prolog.
+    pub fn to_nibbles(&self) -> Vec<u8> {
+	let prolog = self.prolog();
+	let mut nibs: Vec<u8> = Vec::new();
+	for i in 0..5 {
+	    nibs.push(((prolog >> (i * 4)) & 0xf) as u8);
+	}
+	nibs.extend(self.body_nibbles());
+	return nibs;
+    }
+}
+
+// Decode the real, extended real, complex, or extended complex object
+// at the start of `nibs`.
+pub fn read_number(nibs: &[u8]) -> Result<HpNumber, String> {
+    let prolog = hp_object::get_prolog(nibs)?;
+    return HpNumber::from_body(&nibs[5..], prolog);
+}
+
+pub fn decode(path: &PathBuf, exact: &bool) {
+    let result = hp_object::read_object_file(path).and_then(|(_, nibs)| read_number(&nibs));
+    match result {
+	Ok(n) if *exact => println!("{}", n.to_exact_string()),
+	Ok(n) => println!("{}", n),
+	Err(e) => crate::helpers::error_handler(format!("Error: {}", e)),
+    }
+}
+
+// Write `value` as a number object to `output`.
+pub fn encode(value: &str, output: &PathBuf, extended: &bool, overwrite: &bool) -> PathBuf {
+    let final_path = match overwrite {
+	true => output.to_path_buf(),
+	false => crate::helpers::get_unique_path(output.to_path_buf()),
+    };
+    let result = HpNumber::parse(value, *extended)
+	.and_then(|n| hp_object::write_object_file(&final_path, hp_object::DEFAULT_ROMREV, &n.to_nibbles()).map(|_| n));
+    match result {
+	Ok(n) => println!("Wrote {} to {}", style(n).blue().bright(), style(final_path.display()).yellow().bright()),
+	Err(e) => crate::helpers::error_handler(format!("Error: {}", e)),
+    }
+    return final_path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // what the HP 48 shows in STD mode after typing `typed`
+    fn std(typed: &str) -> String {
+	HpReal::parse(typed, 12).unwrap().to_string()
+    }
+
+    #[test]
+    fn integers() {
+	assert_eq!(std("2"), "2");
+	assert_eq!(std("-2"), "-2");
+	assert_eq!(std("0"), "0");
+	assert_eq!(std("123456789012"), "123456789012");
+	assert_eq!(std("100000000000"), "100000000000");
+    }
+
+    #[test]
+    fn scientific() {
+	assert_eq!(std("1E12"), "1.E12");
+	assert_eq!(std("-1E12"), "-1.E12");
+	assert_eq!(std("1.5E12"), "1.5E12");
+	assert_eq!(std("6.02E23"), "6.02E23");
+	assert_eq!(std("1E-499"), "1.E-499");
+    }
+
+    #[test]
+    fn fractions() {
+	assert_eq!(std("1.5E-3"), ".0015");
+	assert_eq!(std("-1.5E-3"), "-.0015");
+	assert_eq!(std(".25"), ".25");
+	assert_eq!(std("-12.5"), "-12.5");
+    }
+
+    #[test]
+    fn rounds_to_12_digits() {
+	assert_eq!(std("1.23456789012345"), "1.23456789012");
+	assert_eq!(std("1.234567890125"), "1.23456789013");
+	assert_eq!(std(".333333333333333"), ".333333333333");
+	assert_eq!(std("9.9999999999996"), "10");
+	assert!(HpReal::parse("9.9999999999996E499", 12).is_err());
+    }
+
+    #[test]
+    fn decodes_nibbles() {
+	// -1.5E-3: exponent 997, mantissa 150000000000, sign 9, all
+	// least significant nibble first
+	let nibs = [7, 9, 9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 1, 9];
+	let r = HpReal::from_nibbles(&nibs, 12).unwrap();
+	assert_eq!(r, HpReal { negative: true, mantissa: 150000000000, exponent: -3, digits: 12 });
+	assert_eq!(r.to_nibbles(), nibs);
+    }
+
+    #[test]
+    fn decimal_strings_are_json_numbers() {
+	assert_eq!(HpReal::parse("1E12", 12).unwrap().to_decimal_string(), "1E12");
+	assert_eq!(HpReal::parse("-.25", 12).unwrap().to_decimal_string(), "-0.25");
+    }
+}
@@ -23,10 +23,11 @@ use std::io::Write;
 
 use serialport;
 use console::style;
-use indicatif::ProgressBar;
 
 const SOH: u8 = 0x01;
 const CR: u8 = 0x0d;
+// how many times to NAK a packet before giving up
+const MAX_RETRIES: u32 = 10;
 
 
 #[derive(Debug)]
@@ -43,8 +44,8 @@ struct KermitPacket {
 impl KermitPacket {
     fn calc_check(&self) -> u8 {
 	let v = self.to_vec();
-	// oddly, index value LEN is the check value
-	v[unchar(self.len) as usize]
+	// the check is right before the CR
+	v[v.len() - 2]
     }
     // calculate check and return full packet including EOL.
     fn to_vec(&self) -> Vec<u8> {
@@ -156,86 +157,74 @@ fn make_generic_packet(seq: &mut u32, ptype: char) -> Vec<u8> {
     return p.to_vec();
 }
 
-// TODO: I don't know why this fails sometimes, but I think it has to
-// do with how we read the packet (3 bytes then rest of packet).
+// Read the next packet, however many pieces it comes in. Anything
+// before the SOH, like the CR that ends each packet, is skipped, and
+// so is an SOH that turns out not to start a packet.
 fn read_packet(port: &mut Box<dyn serialport::SerialPort>) -> Result<KermitPacket, String> {
-    // have to sleep, probably because the calculator is slow
-    std::thread::sleep(std::time::Duration::from_millis(300));
-    // it seems we have to read 3 bytes, then the rest of the packet
-    let mut header: [u8; 3] = [0; 3];
-    match port.read(header.as_mut_slice()) {
-	Ok(_) => {},
-	Err(e) => return Err("failed to read header of packet: ".to_owned() + &e.to_string()),
-    }
-    //println!("header is {:x?}", header);
-    if header[0] != SOH {
-	return Err("malformed Kermit packet (SOH missing)".to_owned());
+    let deadline = crate::serial::deadline(port);
+    let mut buf: Vec<u8> = Vec::new();
+    loop {
+	if let Err(e) = crate::serial::fill(port, &mut buf, 2, deadline) {
+	    return Err("failed to read packet: ".to_owned() + &e);
+	}
+	if buf[0] != SOH {
+	    buf.remove(0);
+	    continue;
+	}
+	// LEN field, which has to cover at least SEQ, TYPE, and CHECK
+	if buf[1] < tochar(3) || buf[1] > tochar(94) {
+	    buf.remove(0);
+	    continue;
+	}
+	// MARK and LEN, then SEQ, TYPE, DATA, and CHECK
+	let end = 2 + unchar(buf[1]) as usize;
+	if let Err(e) = crate::serial::fill(port, &mut buf, end, deadline) {
+	    return Err("failed to read packet data: ".to_owned() + &e);
+	}
+	// control characters are quoted in packets, so another SOH
+	// means this one was cut off and a new one started
+	if let Some(i) = buf[1..end].iter().position(|c| *c == SOH) {
+	    buf.drain(..i + 1);
+	    continue;
+	}
+	let packet = KermitPacket {
+	    len: buf[1],
+	    seq: buf[2],
+	    ptype: buf[3],
+	    data: buf[4..end - 1].to_vec(),
+	};
+	// verify checksum on packet
+	if buf[end - 1] != packet.calc_check() {
+	    return Err("checksum of received data does not match checksum in packet".to_owned());
+	}
+	return Ok(packet);
     }
+}
 
-    // LEN field
-    let len = unchar(header[1]);
-    // this would be len - 1, but we want to also read the CR at the end of the packet.
-    let mut rest_of_packet = vec![0 as u8; len as usize];
-
-    // could probably reduce this delay slightly
-    // this also seems to be needed only for getting files from the calc
-    std::thread::sleep(std::time::Duration::from_millis(50));
-    match port.read(rest_of_packet.as_mut_slice()) {
-	Ok(_) => {},
-	Err(e) => return Err("failed to read packet data: ".to_owned() + &e.to_string()),
+// Write the packet `bytes`, which is called `what` in the error.
+fn write_packet(port: &mut Box<dyn serialport::SerialPort>, bytes: &[u8], what: &str) -> Result<(), String> {
+    match port.write_all(bytes) {
+	Ok(_) => return Ok(()),
+	Err(e) => return Err(format!("failed to write {} packet: {}", what, e)),
     }
-    //println!("rest of packet is {:x?}", rest_of_packet);
-    // subtract 2 to drop 0x0d and check field, to isolate just data
-    // portion and assemble KermitPacket struct.
-    let data_field = rest_of_packet[1..(len as usize - 2)].to_vec();
-    let packet = KermitPacket {
-	// TODO: should len be the `len` variable above, that's been uncharred?
-	len: header[1],
-	seq: header[2],
-	ptype: rest_of_packet[0],
-	// clone to create non-local object, otherwise rx_data goes
-	// out of scope at the end of this function and refuses to
-	// compile
-	data: data_field.clone(),
-    };
-    
-    let rx_checksum = rest_of_packet[len as usize - 3];
-    // verify checksum on packet
-    if rx_checksum != packet.calc_check() {
-	return Err("Error: checksum of received data does not match checksum in packet".to_owned());
-    }
-
-    //println!("packet is {:x?}", packet);
-
-    return Ok(packet);
 }
 
-// This function will exit the entire program on error.
-fn send_packet(p: KermitPacket, bar: &ProgressBar, port: &mut Box<dyn serialport::SerialPort>) {
-    // still bytes left but the packet is shorter
-    //bar.println(format!("p out of loop is {:x?}", p));
-    match port.write(&p.to_vec()) {
-    	Ok(_) => {},
-	Err(e) => {
-	    bar.abandon();
-	    crate::helpers::error_handler(format!("Error: failed to write data packet: {}", e));
-	},
-    }
+// Read the answer to the packet called `what`, which has to be an ACK.
+fn read_ack(port: &mut Box<dyn serialport::SerialPort>, what: &str) -> Result<(), String> {
     match read_packet(port) {
-	Ok(packet) => {
-	    if packet.ptype != 'Y' as u8 {
-		bar.abandon();
-		crate::helpers::error_handler(
-		    "Error: no ACK for data (\"D\") packet. Try sending again.".to_string());
-	    }
-	},
-	Err(e) => {
-	    bar.abandon();
-	    crate::helpers::error_handler(format!("Error: bad \"D\" packet response: {}.", e));
-	},
+	Ok(packet) if packet.ptype == 'Y' as u8 => return Ok(()),
+	Ok(_) => return Err(format!("no ACK for {} packet. Try sending again.", what)),
+	Err(e) => return Err(format!("bad {} packet response: {}.", what, e)),
     }
 }
 
+fn send_packet(p: KermitPacket, port: &mut Box<dyn serialport::SerialPort>) -> Result<(), String> {
+    // packet_delay from the config file, for slow adapters
+    std::thread::sleep(crate::config::packet_delay());
+    write_packet(port, &p.to_vec(), "data (\"D\")")?;
+    return read_ack(port, "data (\"D\")");
+}
+
 // Make a Vec of KermitPackets from the contents of the file, specified in `f`.
 fn make_packet_list(f: Vec<u8>, seq: &mut u32) -> Vec<KermitPacket> {
     let mut packet_list: Vec<KermitPacket> = Vec::new();
@@ -294,112 +283,74 @@ fn make_packet_list(f: Vec<u8>, seq: &mut u32) -> Vec<KermitPacket> {
     return packet_list;
 }
 
-fn finish_server(port: &mut Box<dyn serialport::SerialPort>) {
+pub fn finish_server(port: &mut Box<dyn serialport::SerialPort>) -> Result<(), String> {
     // "I" packet is identical to "S" except for the packet type.
 
     // seq can and probably should be 0, and Rust lets you do `&mut 0`
     // legally. Funky, for sure.
     let i_packet = make_init_packet(&mut 0, 'I');
-    match port.write(&i_packet) {
-	Ok(_) => {},
-	Err(e) => crate::helpers::error_handler(format!("Error: failed to write \"I\" packet: {}", e)),
-    }
-    // could wait for ack but probably don't need to.
-    std::thread::sleep(std::time::Duration::from_millis(300));
+    write_packet(port, &i_packet, "\"I\"")?;
+    // the server ACKs the I packet. Send the finish command even if it
+    // doesn't, like before.
+    let _ = read_packet(port);
     
     // we are sending a 'G' packet with 'F' in the data field,
     // which tells the server to finish.
     // we use 0 as the seq number even though the I packet was also 0.
     let f_packet = vec![SOH, 0x24, tochar(0), 'G' as u8, 'F' as u8, 0x34, CR]; // hardcoded CRC
-    match port.write(&f_packet) {
-	Ok(_) => {},
-	Err(e) => crate::helpers::error_handler(format!("Error: failed to write \"GF\" packet: {}", e)),
-    }
+    return write_packet(port, &f_packet, "\"GF\"");
 }
 
-// TODO: this is pretty unreliable and doesn't work with x48 at full
-// speed. It has to do with the read_packet() function.
-
 // TODO: (more important) need to handle special characters in the filename
 
 // See the top of this file for what this function actually
-// does. There are a lot of match statements, but it's how I catch
-// serial port and protocol errors.
-pub fn send_file(path: &PathBuf, port: &mut Box<dyn serialport::SerialPort>, finish: &bool) {
+// does. Every packet has to be ACKed, and anything else is an error.
+pub fn send_file(path: &PathBuf, port: &mut Box<dyn serialport::SerialPort>, finish: &bool) -> Result<(), String> {
     let mut seq = 0u32;
     
-    let file_contents = crate::helpers::get_file_contents(path);
+    let file_contents = crate::helpers::get_file_contents(path)?;
     
     let s_packet = make_init_packet(&mut seq, 'S');
-    match port.write(&s_packet) {
-	Ok(_) => {},
-	Err(e) => crate::helpers::error_handler(format!("Error: failed to write \"S\" packet: {}", e)),
-    }
-    match read_packet(port) {
-	Ok(packet) => {
-	    if packet.ptype != 'Y' as u8 {
-		crate::helpers::error_handler("Error: no ACK for \"S\" packet. Try sending again.".to_string());
-	    }
-	},
-	Err(e) => crate::helpers::error_handler(format!("Error: bad \"S\" packet response: {}.", e)),
-    }
+    write_packet(port, &s_packet, "\"S\"")?;
+    read_ack(port, "\"S\"")?;
     
     let f_packet = make_f_packet(&mut seq, path.file_name().unwrap());
-    match port.write(&f_packet) {
-    	Ok(_) => {},
-	Err(e) => crate::helpers::error_handler(format!("Error: failed to write \"F\" packet: {}", e)),
-    }
-    
-    match read_packet(port) {
-	Ok(packet) => {
-	    if packet.ptype != 'Y' as u8 {
-		crate::helpers::error_handler("Error: no ACK for \"F\" packet. Try sending again.".to_string());
-	    }
-	},
-	Err(e) => crate::helpers::error_handler(format!("Error: bad \"F\" packet response: {}", e)),
-    }
+    write_packet(port, &f_packet, "\"F\"")?;
+    read_ack(port, "\"F\"")?;
 
     let packet_list = make_packet_list(file_contents, &mut seq);
     let bar = crate::helpers::get_progress_bar(packet_list.len() as u64);
     
-    for p in packet_list {
-	send_packet(p, &bar, port);
-	bar.inc(1);
-    }
-    //bar.println(format!("seq is {seq}"));
-    let z_packet = make_generic_packet(&mut seq, 'Z');
-    match port.write(&z_packet) {
-    	Ok(_) => {},
-	Err(e) => {
-	    // abondon() leaves the progress bar in place, finish() clears it.
-	    bar.abandon();
-	    crate::helpers::error_handler(
-		format!("Error: failed to write \"Z\" (end-of-file) packet: {}", e));
-	},
-    }
-
-    // needed to make sure the calculator gets its packets
-    std::thread::sleep(std::time::Duration::from_millis(300));
-    
-    let b_packet = make_generic_packet(&mut seq, 'B');
-    match port.write(&b_packet) {
-    	Ok(_) => {},
-	Err(e) => {
-	    bar.abandon();
-	    crate::helpers::error_handler(
-		format!("Error: failed to write \"B\" (end-of-transmission) packet: {}", e));
-	},
+    // abandon() leaves the progress bar in place, finish() clears it.
+    let sent = (|| {
+	for p in packet_list {
+	    send_packet(p, port)?;
+	    bar.inc(1);
+	}
+	let z_packet = make_generic_packet(&mut seq, 'Z');
+	write_packet(port, &z_packet, "\"Z\" (end-of-file)")?;
+	read_ack(port, "\"Z\" (end-of-file)")?;
+
+	let b_packet = make_generic_packet(&mut seq, 'B');
+	write_packet(port, &b_packet, "\"B\" (end-of-transmission)")?;
+	return read_ack(port, "\"B\" (end-of-transmission)");
+    })();
+    if sent.is_err() {
+	bar.abandon();
+	return sent;
     }
     bar.finish();
 
     if *finish {
-	finish_server(port);
+	finish_server(port)?;
     }
+    return Ok(());
 }
 
 
 // TODO: indeterminate progress bar or something similar.
-pub fn get_file(path: &PathBuf, port: &mut Box<dyn serialport::SerialPort>, overwrite: &bool) -> PathBuf {
+pub fn get_file(path: &PathBuf, port: &mut Box<dyn serialport::SerialPort>, overwrite: &bool)
+		-> Result<PathBuf, String> {
     let final_path = match overwrite {
 	true => path.to_path_buf(),
 	false => crate::helpers::get_unique_path(path.to_path_buf()),
@@ -411,154 +362,280 @@ pub fn get_file(path: &PathBuf, port: &mut Box<dyn serialport::SerialPort>, over
 		style(final_fname).yellow().bright(),
 		style(port.name().unwrap()).green().bright()));
 
-    
-    let mut seq = 0;
-    let mut out = File::create(&final_path).unwrap();
-
-    // read S packet, which initializes connection from the calculator
-    match read_packet(port) {
-	Ok(packet) => {
-	    if packet.ptype != 'S' as u8 {
-		crate::helpers::error_handler("Error: failed to read \"S\" packet.".to_string());
-	    }
+    let (file_bytes, packet_counter) = match receive_file(port) {
+	Ok(r) => r,
+	Err(e) => {
+	    pb.abandon();
+	    return Err(e);
 	},
-	Err(e) => crate::helpers::error_handler(format!("Error: bad \"S\" packet response: {}.", e)),
+    };
+
+    // only create the file once there's something to put in it
+    if let Err(e) = File::create(&final_path).and_then(|mut out| out.write_all(&file_bytes)) {
+	return Err(format!("failed to write to output file: {:?}", e));
     }
 
-    std::thread::sleep(std::time::Duration::from_millis(300));
-    // ack the S packet with a send-init packet of our own
-    let s_ack_packet = make_init_packet(&mut seq, 'Y');
-    match port.write(&s_ack_packet) {
-    	Ok(_) => {},
-	Err(e) => crate::helpers::error_handler(
-	    format!("Error: failed to write \"Y\" packet for \"S\" packet: {}", e)),
+    pb.finish_with_message(
+	format!("Receiving file as {:?} from {}...{} Got {:?} {}.",
+		style(final_fname).yellow().bright(),
+		style(port.name().unwrap()).green().bright(),
+		style("done!").green().bright(),
+		packet_counter,
+		match packet_counter {
+		    1 => "packet",
+		    _ => "packets",
+		}
+	)
+    );
+
+    return Ok(final_path);
+}
+
+// Sequence number of a packet, 0 to 63.
+fn packet_seq(packet: &KermitPacket) -> u32 {
+    return (packet.seq.wrapping_sub(32) % 64) as u32;
+}
+
+// Read the packet numbered `seq` (mod 64) from the calculator. One
+// with a bad checksum, or none in time, gets a NAK so it is sent
+// again, up to MAX_RETRIES times. If the calculator sends the packet
+// before that again, our ACK for it got lost, so `last_ack` goes out
+// again.
+fn receive_packet(port: &mut Box<dyn serialport::SerialPort>, seq: u32, last_ack: &[u8])
+		  -> Result<KermitPacket, String> {
+    let expected = seq % 64;
+    let mut failures = 0;
+    loop {
+	let error = match read_packet(port) {
+	    Ok(packet) if packet.ptype == 'E' as u8 => {
+		return Err(format!("calculator sent an error: {}", String::from_utf8_lossy(&packet.data)));
+	    },
+	    Ok(packet) if packet_seq(&packet) == expected => return Ok(packet),
+	    Ok(packet) if !last_ack.is_empty() && packet_seq(&packet) == (expected + 63) % 64 => {
+		write_packet(port, last_ack, "repeated \"Y\"")?;
+		continue;
+	    },
+	    Ok(packet) => {
+		return Err(format!("got packet {} when expecting packet {}, giving up.", packet_seq(&packet), expected));
+	    },
+	    Err(e) => e,
+	};
+	failures += 1;
+	if failures > MAX_RETRIES {
+	    return Err(format!("no good packet {} after {} tries ({}), giving up.", expected, failures, error));
+	}
+	eprintln!("Bad or missing packet {}, sending NAK and trying again.", expected);
+	// the NAK has the number of the packet we want
+	let nak_packet = make_generic_packet(&mut seq.clone(), 'N');
+	write_packet(port, &nak_packet, "\"N\"")?;
     }
-    
-    std::thread::sleep(std::time::Duration::from_millis(300));
-    // read F packet, which includes filename
-    match read_packet(port) {
-	Ok(packet) => {
-	    if packet.ptype != 'F' as u8 {
-		crate::helpers::error_handler("Error: failed to read \"F\" packet".to_string());
-	    }
-	},
-	Err(e) => crate::helpers::error_handler(format!("Error: bad \"F\" packet: {}", e)),
+}
+
+// Turn the data field of a D packet back into the bytes that were
+// sent. '#' quotes the next character: a quoted control character is
+// sent as ctl(c), and a quoted '#' (with or without the 8th bit) as
+// itself.
+fn decode_data(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+	let c = data[i];
+	if c != '#' as u8 {
+	    bytes.push(c);
+	    i += 1;
+	    continue;
+	}
+	let next = match data.get(i + 1) {
+	    Some(next) => *next,
+	    None => return Err("\"D\" packet ends with a control prefix".to_string()),
+	};
+	let low_7bits = ctl(next) & 0x7f;
+	if low_7bits <= 31 || low_7bits == 127 {
+	    bytes.push(ctl(next));
+	} else {
+	    bytes.push(next);
+	}
+	i += 2;
     }
+    return Ok(bytes);
+}
+
+// Receive one file sent by the calculator: the S packet, F packet,
+// data, and Z and B packets, ACKing each one. Returns the data and
+// the number of data packets.
+fn receive_file(port: &mut Box<dyn serialport::SerialPort>) -> Result<(Vec<u8>, u32), String> {
+    // number of the next packet, which is also the number of our ACK
+    let mut seq = 0;
+    let mut last_ack: Vec<u8> = Vec::new();
 
-    // generic ack the F packet
-    let f_ack_packet = make_generic_packet(&mut seq, 'Y');
-    match port.write(&f_ack_packet) {
-    	Ok(_) => {},
-	Err(e) => crate::helpers::error_handler(
-	    format!("Error: failed to write \"Y\" packet for \"F\" packet: {}", e)),
+    // read S packet, which initializes connection from the calculator
+    let packet = receive_packet(port, seq, &last_ack)?;
+    if packet.ptype != 'S' as u8 {
+	return Err(format!("expected \"S\" packet, got \"{}\"", packet.ptype as char));
     }
+    // ack the S packet with a send-init packet of our own
+    last_ack = make_init_packet(&mut seq, 'Y');
+    write_packet(port, &last_ack, "\"Y\" for \"S\"")?;
 
     let mut file_bytes: Vec<u8> = Vec::new();
-    let mut packet_counter = 0;
-    
-    loop {
-	let packet: KermitPacket = match read_packet(port) {
-	    Ok(packet) => {
+    let mut packet_counter = 0u32;
+    let mut got_file_header = false;
 
-		if packet.ptype == 'D' as u8 {
-		    packet
-		} else if packet.ptype == 'Z' as u8 {
-		    // Z (end-of-file) is sent by the calc
-		    break;
-		} else {
-		    crate::helpers::error_handler(
-			format!("Error: unexpected packet type when waiting for \"D\" packet."));
-		    KermitPacket {data: Vec::new(), len: 0, ptype: 0u8, seq: 0}
-		}
+    // then F (the filename), D packets, Z (end of file) and B (end
+    // of transfer)
+    loop {
+	let packet = receive_packet(port, seq, &last_ack)?;
+	let ptype = packet.ptype as char;
+	match ptype {
+	    'F' if !got_file_header => got_file_header = true,
+	    'D' if got_file_header => {
+		// convert funky Kermit data format into raw bytes
+		file_bytes.extend(decode_data(&packet.data)?);
+		packet_counter += 1;
 	    },
-	    Err(e) => {
-		crate::helpers::error_handler(format!("Error: bad \"D\" packet: {}.", e));
-		KermitPacket {data: Vec::new(), len: 0, ptype: 0u8, seq: 0}
-	    }
-	};
-
-	// convert funky Kermit data format into raw bytes
-	let mut i = 0;
-	while i < packet.data.len() {
-	    let c = *packet.data.get(i).unwrap();
-	    if c == '#' as u8 {
-		// if the character is a #, then the following char
-		// has low 7 bits <= 31 or == 127, or == '#'. The
-		// following char is also stored as ctl(c).
-
-		file_bytes.push(ctl(*packet.data.get(i+1).unwrap() as u8));
-		i += 2;
-	    } else {
-		file_bytes.push(*packet.data.get(i).unwrap() as u8);
-		i += 1;
-	    }
+	    'Z' | 'B' if got_file_header => {},
+	    _ => return Err(format!("unexpected \"{}\" packet.", ptype)),
 	}
+	last_ack = make_generic_packet(&mut seq, 'Y');
+	write_packet(port, &last_ack, &format!("\"Y\" for \"{}\"", ptype))?;
+	if ptype == 'B' {
+	    break;
+	}
+    }
 
-	// send ACK for this packet
-	let d_ack_packet = make_generic_packet(&mut seq, 'Y');
-	match port.write(&d_ack_packet) {
-    	    Ok(_) => {},
-	    Err(e) => crate::helpers::error_handler(
-		format!("Error: failed to write \"Y\" packet for \"D\" packet: {}", e)),
+    return Ok((file_bytes, packet_counter));
+}
+
+// Make a packet of type `ptype` whose data field is `data`, quoting
+// control characters the same way as make_packet_list().
+fn make_data_packet(seq: &mut u32, ptype: char, data: &[u8]) -> Result<KermitPacket, String> {
+    let mut packet_data: Vec<u8> = Vec::new();
+    for c in data {
+	let low_7bits = c & 0x7f;
+	if low_7bits <= 31 || low_7bits == 127 {
+	    packet_data.push('#' as u8);
+	    packet_data.push(ctl(*c));
+	} else if low_7bits == '#' as u8 {
+	    packet_data.push('#' as u8);
+	    packet_data.push(*c);
+	} else {
+	    packet_data.push(*c);
 	}
-	packet_counter += 1;
     }
+    // LEN has to fit in one printable character
+    if packet_data.len() + 3 > 94 {
+	return Err("command is too long for one Kermit packet".to_owned());
+    }
+    let p = KermitPacket {
+	len: tochar(packet_data.len() as u8 + 3),
+	seq: tochar((*seq as u8) % 64),
+	ptype: ptype as u8,
+	data: packet_data,
+    };
+    *seq += 1;
+    return Ok(p);
+}
 
-    std::thread::sleep(std::time::Duration::from_millis(300));
-    // read Z (EOF) packet from calculator
+// Get the variable `name` from the Kermit server into memory. The R
+// (receive) packet asks the server to send it to us, which then goes
+// just like the calculator's SEND command.
+pub fn get_data(name: &str, port: &mut Box<dyn serialport::SerialPort>) -> Result<Vec<u8>, String> {
+    let hp_name: Vec<u8> = name.chars().map(crate::helpers::char_to_hp_char).collect();
+    let r_packet = make_data_packet(&mut 0, 'R', &hp_name)?;
+    write_packet(port, &r_packet.to_vec(), "\"R\"")?;
+    let pb = crate::helpers::get_spinner(format!("Receiving {}...", style(name).yellow().bright()));
+    let received = receive_file(port);
+    pb.finish_and_clear();
+    let (contents, _) = received?;
+    return Ok(contents);
+}
+
+// Have the Kermit server run `command` with a C (host command) packet.
+pub fn execute(command: &str, port: &mut Box<dyn serialport::SerialPort>) -> Result<(), String> {
+    let data: Vec<u8> = command.chars().map(crate::helpers::char_to_hp_char).collect();
+    let c_packet = make_data_packet(&mut 0, 'C', &data)?;
+    write_packet(port, &c_packet.to_vec(), "\"C\"")?;
     match read_packet(port) {
-	Ok(packet) => {
-	    if packet.ptype != 'Z' as u8 {
-		// TODO: "unexpected packet type" is a great error to throw.
-		crate::helpers::error_handler("Error: unexpected packet type after data packets".to_string());
-	    }
-	},
-	Err(e) => crate::helpers::error_handler(format!("Error: failed to read \"Z\" packet: {}", e)),
+	Ok(packet) if packet.ptype == 'Y' as u8 => return Ok(()),
+	Ok(_) => return Err("server did not accept command.".to_string()),
+	Err(e) => return Err(format!("bad \"C\" packet response: {}", e)),
     }
+}
 
-    let z_ack_packet = make_generic_packet(&mut seq, 'Y');
-    match port.write(&z_ack_packet) {
-    	Ok(_) => {},
-	Err(e) => crate::helpers::error_handler(
-	    format!("Error: failed to write \"Y\" packet for \"Z\" packet: {}", e)),
+// See if a Kermit server is listening on `port`, for `alum detect`.
+// An I (initialize) packet sets up parameters like an S packet, but
+// doesn't start a transfer, so it's harmless. Only a server answers
+// it, not RECV.
+pub fn ping(port: &mut Box<dyn serialport::SerialPort>) -> bool {
+    let i_packet = make_init_packet(&mut 0, 'I');
+    if port.write_all(&i_packet).is_err() {
+	return false;
     }
+    return match read_packet(port) {
+	Ok(packet) => packet.ptype == 'Y' as u8,
+	Err(_) => false,
+    };
+}
 
-    // read B (EOT) packet from calculator
-    match read_packet(port) {
-	Ok(packet) => {
-	    if packet.ptype != 'B' as u8 {
-		crate::helpers::error_handler("Error: unexpected packet type after \"Z\" packet".to_string());
-	    }
-	},
-	Err(e) => crate::helpers::error_handler(format!("Error: failed to read \"B\" packet: {}", e)),
+// For --trace: if `buf` starts with a whole Kermit packet, return its
+// length and a summary of it.
+pub fn describe(buf: &[u8]) -> Option<(usize, String)> {
+    if buf.len() < 5 || buf[0] != SOH || buf[1] < tochar(3) || buf[1] > tochar(94) || buf[2] < 32 {
+	return None;
     }
-
-    let b_ack_packet = make_generic_packet(&mut seq, 'Y');
-    match port.write(&b_ack_packet) {
-    	Ok(_) => {},
-	Err(e) => crate::helpers::error_handler(
-	    format!("Error: failed to write \"Y\" packet for \"B\" packet: {}", e)),
+    let len = unchar(buf[1]) as usize;
+    // MARK and LEN, then LEN bytes, then the CR, which may not be here yet
+    let end = 2 + len;
+    if buf.len() < end {
+	return None;
     }
+    let packet = KermitPacket {
+	len: buf[1],
+	seq: buf[2],
+	ptype: buf[3],
+	data: buf[4..end - 1].to_vec(),
+    };
+    let check = buf[end - 1];
+    let expected = block_check_1(buf[1..end - 1].to_vec());
+    let data: String = packet.data.iter().map(|c| crate::helpers::hp_char_to_char(*c)).collect();
+    let summary = format!("Kermit {} packet, seq {}, {} data bytes {:?}, check {}",
+			  packet.ptype as char, unchar(packet.seq), packet.data.len(), data,
+			  match check == expected {
+			      true => "ok".to_owned(),
+			      false => format!("bad (got {:?}, expected {:?})", check as char, expected as char),
+			  });
+    let consumed = if buf.get(end) == Some(&CR) { end + 1 } else { end };
+    return Some((consumed, summary));
+}
 
-    
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    match out.write_all(&file_bytes) {
-	Ok(_) => {},
-	Err(e) => panic!("Error: failed to write to output file: {:?}", e),
-    };
+    #[test]
+    fn quoted_hash_is_a_hash() {
+	assert_eq!(decode_data(b"a##b").unwrap(), b"a#b");
+	assert_eq!(decode_data(&[b'#', 0xa3]).unwrap(), [0xa3]);
+    }
 
-    pb.finish_with_message(
-	format!("Receiving file as {:?} from {}...{} Got {:?} {}.",
-		style(final_fname).yellow().bright(),
-		style(port.name().unwrap()).green().bright(),
-		style("done!").green().bright(),
-		packet_counter,
-		match packet_counter {
-		    1 => "packet",
-		    _ => "packets",
-		}
-	)
-    );
+    #[test]
+    fn quoted_control_characters() {
+	assert_eq!(decode_data(b"#M#J#@#?").unwrap(), [0x0d, 0x0a, 0x00, 0x7f]);
+	assert_eq!(decode_data(&[b'#', 0xcd]).unwrap(), [0x8d]);
+    }
 
-    return final_path;
+    #[test]
+    fn every_byte_round_trips() {
+	let bytes: Vec<u8> = (0..=255).collect();
+	// small enough pieces that each fits in one packet
+	for piece in bytes.chunks(32) {
+	    let packet = make_data_packet(&mut 0, 'D', piece).unwrap();
+	    assert_eq!(decode_data(&packet.data).unwrap(), piece);
+	}
+    }
+
+    #[test]
+    fn prefix_at_the_end_is_an_error() {
+	assert!(decode_data(b"ab#").is_err());
+    }
 }
@@ -0,0 +1,332 @@
+// Library (DOLIB, prolog 0x2b40) inspection. A library looks like
+// this:
+/*
+ * prolog (5 nibbles)
+ * size of everything after the prolog (5 nibbles)
+ * title length (2 nibbles), title, title length again (omitted if
+ *   the title is empty)
+ * library number (3 nibbles)
+ * offsets to the hash table, message table, link table, and config
+ *   object (5 nibbles each, 0 if not present)
+ * the objects of the library
+ * library CRC (4 nibbles)
+ */
+// Like every other offset in RPL, the table offsets are relative to
+// the address of the offset field itself.
+
+// The hash table is a hex string holding the command names: 16
+// offsets (one for each name length), the length of the name table,
+// the names themselves as <length, name, 3-nibble command number>,
+// and then a table of offsets back to the names. The link table is a
+// hex string with one offset per command pointing to its object. The
+// message table is an array of strings.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use console::style;
+
+use crate::hp_object;
+
+pub struct Library {
+    pub number: u32,
+    pub title: String,
+    // indexed by command number, None for commands without names
+    pub commands: Vec<Option<String>>,
+    // start and length of each command's object
+    pub command_objects: Vec<(usize, usize)>,
+    pub config: Option<(usize, usize)>,
+    pub messages: Vec<String>,
+    // other libraries referenced with XLIB (ROM pointer) objects
+    pub uses: BTreeSet<u32>,
+}
+
+// Follow the offset stored at `field`. Returns None for a 0 offset.
+fn follow_offset(nibs: &[u8], field: usize) -> Result<Option<usize>, String> {
+    match hp_object::read_nibbles(nibs, field, 5)? {
+	0 => Ok(None),
+	offset => {
+	    let target = field + offset as usize;
+	    if target >= nibs.len() {
+		return Err(format!("offset at nibble {} points past the end of the library", field));
+	    }
+	    Ok(Some(target))
+	},
+    }
+}
+
+// Size of the object at `start`, which has to end inside the library.
+fn object_len(nibs: &[u8], start: usize) -> Result<usize, String> {
+    let len = hp_object::calc_object_size(&nibs[start..])? as usize;
+    if start + len > nibs.len() {
+	return Err(format!("object at nibble {} runs past the end of the library", start));
+    }
+    return Ok(len);
+}
+
+// Read the names in the hash table at `start`.
+fn read_hash_table(nibs: &[u8], start: usize, names: &mut BTreeMap<u32, String>) -> Result<(), String> {
+    let table_end = start + 5 + hp_object::read_nibbles(nibs, start + 5, 5)? as usize;
+    // skip prolog, size, and the 16 offsets by name length
+    let names_field = start + 10 + 16 * 5;
+    let names_end = names_field + hp_object::read_nibbles(nibs, names_field, 5)? as usize;
+    if names_end > table_end {
+	return Err("hash table name list is longer than the hash table".to_owned());
+    }
+
+    let mut index = names_field + 5;
+    while index < names_end {
+	let char_len = hp_object::read_nibbles(nibs, index, 2)? as usize;
+	let name = hp_object::read_hp_string(nibs, index + 2, char_len)?;
+	let cmd = hp_object::read_nibbles(nibs, index + 2 + char_len * 2, 3)?;
+	names.insert(cmd, name);
+	index += 2 + char_len * 2 + 3;
+    }
+    return Ok(());
+}
+
+// Read the strings in the message table array at `start`.
+fn read_message_table(nibs: &[u8], start: usize) -> Result<Vec<String>, String> {
+    let mut messages: Vec<String> = Vec::new();
+    if hp_object::get_prolog(&nibs[start..])? != 0x29e8 {
+	return Err("message table is not an array".to_owned());
+    }
+    let array_end = start + 5 + hp_object::read_nibbles(nibs, start + 5, 5)? as usize;
+    if hp_object::read_nibbles(nibs, start + 10, 5)? != 0x2a2c {
+	return Err("message table is not an array of strings".to_owned());
+    }
+    // a message table is one-dimensional, but skip over however many
+    // dimensions there are
+    let dims = hp_object::read_nibbles(nibs, start + 15, 5)? as usize;
+    let mut index = start + 20 + dims * 5;
+    // array elements don't have their own prologs, just sizes
+    while index < array_end {
+	let size = hp_object::read_nibbles(nibs, index, 5)? as usize;
+	if size < 5 {
+	    return Err("message table string has a bad size field".to_owned());
+	}
+	messages.push(hp_object::read_hp_string(nibs, index + 5, (size - 5) / 2)?);
+	index += size;
+    }
+    return Ok(messages);
+}
+
+// Find XLIB references to other libraries in the object at the start
+// of `nibs`, looking inside composite objects.
+fn find_rompointers(nibs: &[u8], uses: &mut BTreeSet<u32>) -> Result<(), String> {
+    match hp_object::get_prolog(nibs)? {
+	// DOROMP: library number, then command number
+	0x2e92 => {
+	    uses.insert(hp_object::read_nibbles(nibs, 5, 3)?);
+	},
+	// composite objects
+	0x2d9d | 0x2a74 | 0x2ab8 | 0x2ada => {
+	    for (start, _) in hp_object::composite_elements(nibs)? {
+		find_rompointers(&nibs[start..], uses)?;
+	    }
+	},
+	_ => {},
+    }
+    return Ok(());
+}
+
+pub fn read_library(nibs: &[u8]) -> Result<Library, String> {
+    if hp_object::get_prolog(nibs)? != 0x2b40 {
+	return Err("object is not a library".to_owned());
+    }
+    let lib_len = hp_object::calc_object_size(nibs)? as usize;
+    if lib_len > nibs.len() {
+	return Err("library length is greater than file size; file may be corrupt".to_owned());
+    }
+    let nibs = &nibs[0..lib_len];
+
+    let title_len = hp_object::read_nibbles(nibs, 10, 2)? as usize;
+    let title = hp_object::read_hp_string(nibs, 12, title_len)?;
+    let number_field = match title_len {
+	0 => 12,
+	_ => 12 + title_len * 2 + 2,
+    };
+    let number = hp_object::read_nibbles(nibs, number_field, 3)?;
+    let hash_field = number_field + 3;
+
+    let mut names: BTreeMap<u32, String> = BTreeMap::new();
+    if let Some(hash) = follow_offset(nibs, hash_field)? {
+	read_hash_table(nibs, hash, &mut names)?;
+    }
+
+    let messages = match follow_offset(nibs, hash_field + 5)? {
+	Some(start) => read_message_table(nibs, start)?,
+	None => Vec::new(),
+    };
+
+    let mut commands: Vec<Option<String>> = Vec::new();
+    let mut command_objects: Vec<(usize, usize)> = Vec::new();
+    let mut uses: BTreeSet<u32> = BTreeSet::new();
+    if let Some(link) = follow_offset(nibs, hash_field + 10)? {
+	let link_len = hp_object::read_nibbles(nibs, link + 5, 5)? as usize;
+	let count = link_len.saturating_sub(5) / 5;
+	for cmd in 0..count {
+	    let start = match follow_offset(nibs, link + 10 + cmd * 5)? {
+		Some(s) => s,
+		None => return Err(format!("command {} has no object in link table", cmd)),
+	    };
+	    let len = object_len(nibs, start)?;
+	    find_rompointers(&nibs[start..start + len], &mut uses)?;
+	    command_objects.push((start, len));
+	    commands.push(names.remove(&(cmd as u32)));
+	}
+    }
+
+    let config = match follow_offset(nibs, hash_field + 15)? {
+	Some(start) => {
+	    let len = object_len(nibs, start)?;
+	    find_rompointers(&nibs[start..start + len], &mut uses)?;
+	    Some((start, len))
+	},
+	None => None,
+    };
+
+    // a library calling its own commands doesn't need anything else
+    uses.remove(&number);
+
+    return Ok(Library { number, title, commands, command_objects, config, messages, uses });
+}
+
+fn print_library(lib: &Library, nibs: &[u8]) {
+    println!("Library {} {}",
+	     style(lib.number).blue().bright(),
+	     style(format!("{:?}", lib.title)).yellow().bright());
+
+    println!("  Commands ({}):", lib.commands.len());
+    for (cmd, name) in lib.commands.iter().enumerate() {
+	let (start, len) = lib.command_objects[cmd];
+	let prolog = hp_object::get_prolog(&nibs[start..]).unwrap();
+	println!("    {:>4} {:<16} {:<9} {:.1} bytes",
+		 cmd,
+		 match name {
+		     Some(n) => n.to_owned(),
+		     // hidden commands can only be run through XLIB
+		     None => "(unnamed)".to_owned(),
+		 },
+		 hp_object::prolog_name(prolog).unwrap_or("?"),
+		 len as f32 / 2.0);
+    }
+
+    match lib.config {
+	Some((start, len)) => {
+	    let prolog = hp_object::get_prolog(&nibs[start..]).unwrap();
+	    println!("  Config object: {}, {:.1} bytes",
+		     hp_object::prolog_name(prolog).unwrap_or("unknown type"),
+		     len as f32 / 2.0);
+	},
+	None => println!("  Config object: none"),
+    }
+
+    if lib.messages.is_empty() {
+	println!("  Messages: none");
+    } else {
+	println!("  Messages ({}):", lib.messages.len());
+	for (pos, m) in lib.messages.iter().enumerate() {
+	    // message numbers are 1-indexed, as used by DO ERR
+	    println!("    {:>4} {:?}", pos + 1, m);
+	}
+    }
+
+    // A library doesn't say which port it has to be stored in, so the
+    // closest thing is the libraries it calls, which have to be
+    // installed too (in any port).
+    if lib.uses.is_empty() {
+	println!("  Calls libraries (XLIB): none");
+    } else {
+	let numbers: Vec<String> = lib.uses.iter().map(|n| n.to_string()).collect();
+	println!("  Calls libraries (XLIB): {} (install these too; a library doesn't record which port it needs)",
+		 numbers.join(", "));
+    }
+}
+
+// Show the contents of every library in `paths` and warn about any
+// that share a library number, since only one of them can be attached
+// at a time.
+pub fn info(paths: &Vec<PathBuf>) {
+    let mut numbers: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    for path in paths {
+	let (_, nibs) = match hp_object::read_object_file(path) {
+	    Ok(f) => f,
+	    Err(e) => return crate::helpers::error_handler(format!("Error: {}", e)),
+	};
+	let lib = match read_library(&nibs) {
+	    Ok(l) => l,
+	    Err(e) => return crate::helpers::error_handler(format!("Error: {}: {}", path.display(), e)),
+	};
+	if paths.len() > 1 {
+	    println!("{}:", style(path.display()).yellow().bright());
+	}
+	print_library(&lib, &nibs);
+	numbers.entry(lib.number).or_default().push(path.display().to_string());
+    }
+
+    for (number, users) in numbers {
+	if users.len() > 1 {
+	    println!("{}: library number {} is used by {}",
+		     style("warning").yellow().bright(),
+		     style(number).blue().bright(),
+		     users.join(", "));
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hp_object::nibbles;
+
+    // `value` as a field of `count` nibbles, in stored order
+    fn field(value: usize, count: usize) -> String {
+	(0..count).map(|i| format!("{:X}", (value >> (i * 4)) & 0xf)).collect()
+    }
+
+    // Library 1280 titled "T", with one command named A whose program
+    // calls XLIB 1234 0 and its own XLIB 1280 1.
+    fn library() -> Vec<u8> {
+	// name list: length, then 'A' as command 0
+	let names = format!("{} 10 14 000", field(12, 5));
+	let hash = format!("E4A20 {} {} {}", field(5 + 80 + 12, 5), "0".repeat(80), names);
+	let command = "D9D20 29E20 2D4 000 29E20 005 100 B2130";
+	let hash_start = 39;
+	let link_start = hash_start + nibbles(&hash).len();
+	let link = format!("E4A20 {} {}", field(10, 5), field(5, 5));
+	let end = link_start + 15 + nibbles(command).len() + 4;
+	nibbles(&format!("04B20 {} 10 45 10 005 {} 00000 {} 00000 {} {} {} 0000",
+			 field(end - 5, 5), field(hash_start - 19, 5), field(link_start - 29, 5),
+			 hash, link, command))
+    }
+
+    #[test]
+    fn reads_number_title_and_commands() {
+	let lib = read_library(&library()).unwrap();
+	assert_eq!(lib.number, 1280);
+	assert_eq!(lib.title, "T");
+	assert_eq!(lib.commands, vec![Some("A".to_owned())]);
+	assert!(lib.config.is_none());
+	assert!(lib.messages.is_empty());
+    }
+
+    #[test]
+    fn finds_other_libraries_it_calls() {
+	let lib = read_library(&library()).unwrap();
+	// not 1280, which is itself
+	assert_eq!(lib.uses.into_iter().collect::<Vec<u32>>(), vec![1234]);
+    }
+
+    #[test]
+    fn cut_short_library_is_an_error() {
+	let mut nibs = library();
+	nibs.truncate(nibs.len() - 10);
+	assert!(read_library(&nibs).is_err());
+    }
+
+    #[test]
+    fn not_a_library() {
+	assert_eq!(read_library(&nibbles("D9D20 B2130")).err(), Some("object is not a library".to_owned()));
+    }
+}
@@ -2,8 +2,29 @@ mod xmodem;
 mod hp_object;
 mod kermit;
 mod helpers;
+mod backup;
+mod library;
+mod grob;
+mod manifest;
+mod hp_real;
+mod config;
+mod data;
+mod deploy;
+mod detect;
+mod diff;
+mod disasm;
+mod dump;
+mod fix;
+mod entries;
+mod sysrpl;
+mod trace;
+mod replay;
+mod serial;
+mod server;
+mod shell;
+mod sync;
+mod watch;
 
-use std::time::Duration;
 use std::path::PathBuf;
 
 use clap::{Parser, Subcommand};
@@ -20,15 +41,44 @@ struct Cli {
 
     // No default_value_t needed to declare that the argument is
     // optional if the argument is of type Option
-    /// Serial port to use for data transfer
+    /// Serial port to use for data transfer (xsend and ksend take several)
     #[clap(short, long, value_parser)]
-    port: Option<PathBuf>,
+    port: Vec<PathBuf>,
 
     /// Baud rate to use on port
     #[clap(short, long, value_parser)]
     #[clap(value_parser = clap::value_parser!(u32).range(1..))]
     baud: Option<u32>,
 
+    /// Data bits per character
+    #[clap(long, value_parser = clap::value_parser!(u8).range(5..=8))]
+    data_bits: Option<u8>,
+
+    /// Parity, as set in the calculator's IOPAR
+    #[clap(long, value_enum)]
+    parity: Option<config::Parity>,
+
+    /// Stop bits
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..=2))]
+    stop_bits: Option<u8>,
+
+    /// Flow control (software is XON/XOFF, hardware is RTS/CTS)
+    #[clap(long, value_enum)]
+    flow_control: Option<config::FlowControl>,
+
+    /// Profile from the config file to use (port, baud, and defaults)
+    #[clap(short = 'P', long, value_parser)]
+    profile: Option<String>,
+
+    /// Log every byte sent and received, with decoded packets, to this file
+    #[clap(long, value_parser)]
+    trace: Option<PathBuf>,
+
+    /// Play back a capture from --trace instead of using real ports,
+    /// and check that Alum writes the same bytes
+    #[clap(long, value_parser)]
+    replay: Option<PathBuf>,
+
 }
 
 
@@ -43,9 +93,27 @@ enum Commands {
 	#[arg(default_value = "")]
 	path: std::path::PathBuf,
 
+	/// More files or directories to watch
+	#[arg(requires = "watch")]
+	more: Vec<PathBuf>,
+
+	/// Keep port open and send files again when they change (needs server)
+	#[clap(short, long, action, default_value_t = false, conflicts_with = "finish")]
+	watch: bool,
+
+	/// Command to compile changed source files with, like "rplcomp {in} {out}"
+	#[clap(short, long, value_parser, requires = "watch")]
+	compile: Option<String>,
+
 	/// Finish Kermit server after file transfer
 	#[clap(short, long, action, default_value_t = false)]
 	finish: bool,
+	/// Don't finish the server, even if the profile says to
+	#[clap(long, action, default_value_t = false, overrides_with = "finish")]
+	no_finish: bool,
+	/// Send to every USB serial port at once
+	#[clap(short, long, action, default_value_t = false, conflicts_with = "watch")]
+	all_ports: bool,
     },
     
     /// Send file with XModem
@@ -53,13 +121,31 @@ enum Commands {
 	#[arg(default_value = "")]
 	path: std::path::PathBuf,
 
+	/// More files or directories to watch
+	#[arg(requires = "watch")]
+	more: Vec<PathBuf>,
+
+	/// Keep port open and send files again when they change (needs server)
+	#[clap(short, long, action, default_value_t = false, conflicts_with = "finish")]
+	watch: bool,
+
+	/// Command to compile changed source files with, like "rplcomp {in} {out}"
+	#[clap(short, long, value_parser, requires = "watch")]
+	compile: Option<String>,
+
 	/// Send to direct XRECV, not XModem server
-	#[clap(short, long, action, default_value_t = false)]
+	#[clap(short, long, action, default_value_t = false, conflicts_with = "watch")]
 	direct: bool,
 
 	/// Finish XModem server after file transfer
 	#[clap(short, long, action, default_value_t = false)]
 	finish: bool,
+	/// Don't finish the server, even if the profile says to
+	#[clap(long, action, default_value_t = false, overrides_with = "finish")]
+	no_finish: bool,
+	/// Send to every USB serial port at once
+	#[clap(short, long, action, default_value_t = false, conflicts_with = "watch")]
+	all_ports: bool,
     },
 
     /// Get file from SEND or ARCHIVE command (not server!)
@@ -70,6 +156,9 @@ enum Commands {
 	/// Overwrite pre-existing file on computer if necessary
 	#[clap(short, long, action, default_value_t = false)]
 	overwrite: bool,
+	/// Don't overwrite, even if the profile says to
+	#[clap(long, action, default_value_t = false, overrides_with = "overwrite")]
+	no_overwrite: bool,
     },
 
     /// Get file with XModem
@@ -80,6 +169,9 @@ enum Commands {
 	/// Overwrite pre-existing file on computer if necessary
 	#[clap(short, long, action, default_value_t = false)]
 	overwrite: bool,
+	/// Don't overwrite, even if the profile says to
+	#[clap(long, action, default_value_t = false, overrides_with = "overwrite")]
+	no_overwrite: bool,
 
 	/// Get from direct XSEND, not XMODEM server
 	#[clap(short, long, action, default_value_t = false)]
@@ -88,83 +180,471 @@ enum Commands {
 	/// Finish XModem server after file transfer
 	#[clap(short, long, action, default_value_t = false)]
 	finish: bool,
+	/// Don't finish the server, even if the profile says to
+	#[clap(long, action, default_value_t = false, overrides_with = "finish")]
+	no_finish: bool,
     },
 
-    /// Run HP object info check on `path` instead of transferring file
+    /// Run HP object info check on files and directories instead of transferring file
     Info {
+	paths: Vec<PathBuf>,
+
+	/// Output format
+	#[clap(short = 'F', long, value_enum, default_value_t = hp_object::InfoFormat::Text)]
+	format: hp_object::InfoFormat,
+
+	/// Write manifest of path, type, size, and CRC of every object
+	#[clap(short, long, value_parser)]
+	manifest: Option<PathBuf>,
+
+	/// Re-check objects listed in a manifest
+	#[clap(short, long, value_parser, conflicts_with_all = ["paths", "manifest"])]
+	check: Option<PathBuf>,
+
+	/// Fail on trailing data after the object
+	#[clap(short, long, action, default_value_t = false)]
+	strict: bool,
+    },
+
+    /// Show number, title, commands, and messages of libraries
+    LibInfo {
+	#[arg(required = true)]
+	paths: Vec<PathBuf>,
+    },
+
+    /// Decode or make real and complex number objects
+    Number {
+	#[clap(subcommand)]
+	command: NumberCommands,
+    },
+
+    /// Convert arrays and lists to and from CSV and JSON
+    Data {
+	#[clap(subcommand)]
+	command: DataCommands,
+    },
+
+    /// Disassemble machine code in code objects, programs, and libraries
+    Disasm {
+	#[arg(default_value = "")]
+	path: PathBuf,
+
+	/// Decompile whole object as System RPL, with named ROM entries
+	#[clap(short, long, action, default_value_t = false)]
+	sysrpl: bool,
+    },
+
+    /// Show what changed between two objects, element by element
+    Diff {
+	#[arg(default_value = "")]
+	old: PathBuf,
+
+	#[arg(default_value = "")]
+	new: PathBuf,
+    },
+
+    /// Print every field of an object with its nibble offset
+    Dump {
+	#[arg(default_value = "")]
+	path: PathBuf,
+    },
+
+    /// Check object for damage and write a repaired copy
+    Fix {
+	#[arg(default_value = "")]
+	path: PathBuf,
+
+	/// Output file (default is next to the original)
+	#[clap(short = 'O', long, value_parser)]
+	output: Option<PathBuf>,
+
+	/// Overwrite pre-existing file on computer if necessary
+	#[clap(short, long, action, default_value_t = false)]
+	overwrite: bool,
+    },
+
+    /// Interactive session with the XModem or Kermit server
+    Shell {
+	/// Talk to the Kermit server instead of the XModem server
+	#[clap(short, long, action, default_value_t = false)]
+	kermit: bool,
+	/// Talk to the XModem server, even if the profile says Kermit
+	#[clap(short, long, action, default_value_t = false, overrides_with = "kermit")]
+	xmodem: bool,
+
+	/// Finish server when leaving the shell
+	#[clap(short, long, action, default_value_t = false)]
+	finish: bool,
+	/// Don't finish the server, even if the profile says to
+	#[clap(long, action, default_value_t = false, overrides_with = "finish")]
+	no_finish: bool,
+    },
+
+    /// Find which port (and speed) has a calculator server
+    Detect {
+	/// Try every common baud rate, not just the one from --baud
+	#[clap(short, long, action, default_value_t = false)]
+	all_bauds: bool,
+    },
+
+    /// Make a folder and the current calculator directory match
+    Sync {
+	#[arg(default_value = "")]
+	dir: PathBuf,
+
+	/// Which side to change
+	#[clap(short, long, value_enum, default_value_t = sync::SyncMode::Push)]
+	mode: sync::SyncMode,
+
+	/// Also delete what isn't on the other side (push: variables, pull: files)
+	#[clap(short, long, action, default_value_t = false)]
+	delete: bool,
+
+	/// Show what would be transferred and deleted without doing it
+	#[clap(short = 'n', long, action, default_value_t = false)]
+	dry_run: bool,
+
+	/// Talk to the Kermit server instead of the XModem server
+	#[clap(short, long, action, default_value_t = false)]
+	kermit: bool,
+	/// Talk to the XModem server, even if the profile says Kermit
+	#[clap(short, long, action, default_value_t = false, overrides_with = "kermit")]
+	xmodem: bool,
+
+	/// Finish server after syncing
+	#[clap(short, long, action, default_value_t = false)]
+	finish: bool,
+	/// Don't finish the server, even if the profile says to
+	#[clap(long, action, default_value_t = false, overrides_with = "finish")]
+	no_finish: bool,
+    },
+
+    /// Inspect or extract from an ARCHIVE backup
+    Backup {
+	#[clap(subcommand)]
+	command: BackupCommands,
+    },
+
+    /// Convert between GROB objects and PNG or PBM images
+    Grob {
+	#[clap(subcommand)]
+	command: GrobCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum NumberCommands {
+    /// Print number object as the calculator shows it in STD mode
+    Decode {
+	#[arg(default_value = "")]
+	path: PathBuf,
+
+	/// Print every mantissa digit and the exponent instead
+	#[clap(short, long, action, default_value_t = false)]
+	exact: bool,
+    },
+
+    /// Make number object from decimal like 1.5, -2E-3, or (1,2)
+    Encode {
+	#[arg(default_value = "", allow_hyphen_values = true)]
+	value: String,
+
+	/// Number object to write
+	#[arg(default_value = "")]
+	output: PathBuf,
+
+	/// Make extended real or complex (15 digits)
+	#[clap(short, long, action, default_value_t = false)]
+	extended: bool,
+
+	/// Overwrite pre-existing file on computer if necessary
+	#[clap(short, long, action, default_value_t = false)]
+	overwrite: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum DataCommands {
+    /// Convert array or list object to CSV or JSON (format from OUTPUT extension)
+    Export {
+	#[arg(default_value = "")]
+	path: PathBuf,
+
+	/// File to write, ending in .csv or .json
+	#[arg(default_value = "")]
+	output: PathBuf,
+
+	/// Overwrite pre-existing file on computer if necessary
+	#[clap(short, long, action, default_value_t = false)]
+	overwrite: bool,
+    },
+
+    /// Make array or list object from CSV file
+    Import {
 	#[arg(default_value = "")]
 	path: PathBuf,
+
+	/// Object to write
+	#[arg(default_value = "")]
+	output: PathBuf,
+
+	/// Type of object to make
+	#[clap(short, long, value_enum, default_value_t = data::ImportKind::Matrix)]
+	to: data::ImportKind,
+
+	/// Make extended reals and complexes (15 digits)
+	#[clap(short, long, action, default_value_t = false)]
+	extended: bool,
+
+	/// Overwrite pre-existing file on computer if necessary
+	#[clap(short, long, action, default_value_t = false)]
+	overwrite: bool,
     },
 }
 
+#[derive(Subcommand, Debug)]
+enum GrobCommands {
+    /// Convert GROB object to image (format from OUTPUT extension)
+    Export {
+	#[arg(default_value = "")]
+	path: PathBuf,
 
-fn get_serial_port(cli_port: Option<PathBuf>, cli_baud: Option<u32>) -> Box<dyn serialport::SerialPort> {
-    let discovered_ports = serialport::available_ports().expect("No ports found!");
-    
-    let mut usb_serial_ports: Vec<serialport::SerialPortInfo> = Vec::new();
+	/// Image to write, ending in .png or .pbm
+	#[arg(default_value = "")]
+	output: PathBuf,
+
+	/// Overwrite pre-existing file on computer if necessary
+	#[clap(short, long, action, default_value_t = false)]
+	overwrite: bool,
+    },
+
+    /// Make GROB object from monochrome PNG or PBM image
+    Import {
+	#[arg(default_value = "")]
+	path: PathBuf,
+
+	/// GROB object to write
+	#[arg(default_value = "")]
+	output: PathBuf,
+
+	/// Overwrite pre-existing file on computer if necessary
+	#[clap(short, long, action, default_value_t = false)]
+	overwrite: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum BackupCommands {
+    /// List variables, libraries, and flags in backup
+    List {
+	#[arg(default_value = "")]
+	path: PathBuf,
+    },
+
+    /// Extract one variable from backup as a standalone object
+    Extract {
+	#[arg(default_value = "")]
+	path: PathBuf,
+
+	/// Variable to extract, with subdirectories separated by '/'
+	#[arg(default_value = "")]
+	name: String,
+
+	/// Output file (default is the variable name)
+	#[clap(short = 'O', long, value_parser)]
+	output: Option<PathBuf>,
+
+	/// Overwrite pre-existing file on computer if necessary
+	#[clap(short, long, action, default_value_t = false)]
+	overwrite: bool,
+    },
+}
 
-    // Sort through the ports and find only USB serial
-    // ports. Sometimes other ports are present, and it's quite
-    // unlikely that they would be for the calculator
+
+// Names of the USB serial ports on this computer. Sometimes other
+// ports are present, and it's quite unlikely that they would be for
+// the calculator.
+fn usb_serial_ports() -> Vec<String> {
+    // when replaying, the ports in the capture are the ones plugged in
+    if replay::is_replaying() {
+	return replay::ports();
+    }
+    let discovered_ports = serialport::available_ports().expect("No ports found!");
+    let mut usb_serial_ports: Vec<String> = Vec::new();
     for p in &discovered_ports {
 	match p.port_type {
 	    serialport::SerialPortType::UsbPort(..) => {
-		usb_serial_ports.push(p.clone());
+		usb_serial_ports.push(p.port_name.clone());
 	    },
 	    _ => {},
 	}
     }
+    return usb_serial_ports;
+}
+
+// Only xsend and ksend can use more than one port.
+fn one_port(ports: &Vec<PathBuf>) -> Option<PathBuf> {
+    if ports.len() > 1 {
+	helpers::error_handler("Error: only xsend and ksend can use more than one port.".to_string());
+    }
+    return ports.first().cloned();
+}
+
+fn open_port(name: &str, baud: Option<u32>, line: &config::LineSettings) -> Result<Box<dyn serialport::SerialPort>, String> {
+    // assume 9600 because that's the default on the 48, and probably others
+    let final_baud = baud.unwrap_or(9600);
+    if replay::is_replaying() {
+	return replay::open(name, final_baud, config::timeout()).map(trace::wrap);
+    }
+    let builder = line.apply(serialport::new(name, final_baud).timeout(config::timeout()));
+    return match builder.open() {
+	Ok(port) => Ok(trace::wrap(port)),
+	// e.description is a string,
+	Err(e) => Err(format!("Error: failed to open port: {}", e.description)),
+    };
+}
+
+// For sending to several calculators at once: the ports from
+// `--port`, or every USB serial port.
+fn get_serial_ports(cli_ports: &Vec<PathBuf>, all_ports: &bool, cli_baud: Option<u32>, line: &config::LineSettings)
+		    -> Vec<(String, Result<Box<dyn serialport::SerialPort>, String>)> {
+    let names: Vec<String> = match all_ports {
+	true => usb_serial_ports(),
+	false => cli_ports.iter().map(|p| p.to_str().unwrap().to_string()).collect(),
+    };
+    if names.is_empty() {
+	helpers::error_handler("Error: no USB serial ports found!".to_string());
+    }
+    return names.into_iter().map(|name| {
+	let port = open_port(&name, cli_baud, line);
+	(name, port)
+    }).collect();
+}
+
+// `probe` is for the commands that talk to a server: only those may
+// ask around when there are several ports, since the probes are
+// packets that a calculator waiting in kget or xget -d would take as
+// the start of a transfer.
+fn get_serial_port(cli_port: Option<PathBuf>, cli_baud: Option<u32>, line: &config::LineSettings, probe: bool)
+		   -> Box<dyn serialport::SerialPort> {
+    let usb_serial_ports = usb_serial_ports();
     
-    //println!("discovered_ports is {:?}", discovered_ports);
-    
-    let final_port = {
-	if cli_port == None {
-	    if usb_serial_ports.len() == 0 {
+    let final_port = match cli_port {
+	Some(p) => std::string::String::from(p.to_str().unwrap()),
+	None => match usb_serial_ports.len() {
+	    0 => {
 		println!("no port specified, no port found!");
 		std::process::exit(1);
-	    } else {
-		// use first port from discovered_ports
-		// use .clone() to get copyable String (from https://stackoverflow.com/a/38305901)
-		discovered_ports.get(0).unwrap().port_name.clone()
-	    }
-	} else {
-	    std::string::String::from(cli_port.unwrap().to_str().unwrap())
-	}
-    };
-
-    let final_baud = {
-	if cli_baud == None {
-	    9600 // assume 9600 because that's the default on the 48, and probably others
-	} else {
-	    cli_baud.unwrap()
-	}
+	    },
+	    1 => usb_serial_ports[0].clone(),
+	    _ if !probe => {
+		println!("{}: several serial ports found, using {} (choose with --port)",
+			 style("warning").yellow().bright(), usb_serial_ports[0]);
+		usb_serial_ports[0].clone()
+	    },
+	    // with more than one, ask each one if there's a calculator server on it
+	    _ => match detect::find_calculator(get_serial_ports(&Vec::new(), &true, cli_baud, line)) {
+		Some(name) => name,
+		None => {
+		    println!("{}: no calculator server answered, using {}",
+			     style("warning").yellow().bright(), usb_serial_ports[0]);
+		    usb_serial_ports[0].clone()
+		},
+	    },
+	},
     };
 
     // This is not how I would normally write a match statement, but I
     // didn't want to deal with the return type in the Err arm.
-    let port = serialport::new(final_port, final_baud)
-	.timeout(Duration::from_millis(3500))
-	.open();
+    let port = open_port(&final_port, cli_baud, line);
     match port {
-	// e.description is a string,
-	Err(ref e) => crate::helpers::error_handler(format!("Error: failed to open port: {}", e.description)),
+	Err(ref e) => crate::helpers::error_handler(e.to_string()),
 	_ => {},
     }
     return port.unwrap();
 
 }
-// The finish argument is to be ignored (and a message printed) if the
-// direct flag is set. That is the only time---again, so simple
-// compared to HPex.
+// Fill in what the command line left out from the profile. The
+// profile's finish, overwrite and protocol are defaults that -f, -o
+// and -k can only add to; --no-finish, --no-overwrite and --xmodem
+// turn them off again.
+fn apply_profile(cli: &mut Cli, profile: &config::Profile) {
+    if cli.port.is_empty() {
+	if let Some(port) = &profile.port {
+	    cli.port.push(port.to_path_buf());
+	}
+    }
+    cli.baud = cli.baud.or(profile.baud);
+    cli.data_bits = cli.data_bits.or(profile.data_bits);
+    cli.parity = cli.parity.or(profile.parity);
+    cli.stop_bits = cli.stop_bits.or(profile.stop_bits);
+    cli.flow_control = cli.flow_control.or(profile.flow_control);
+
+    let kermit_default = profile.protocol == Some(config::Protocol::Kermit);
+    let finish_default = profile.finish.unwrap_or(false);
+    let overwrite_default = profile.overwrite.unwrap_or(false);
+    // the --no-... flags (and --xmodem) turn off what the profile turns on
+    match &mut cli.command {
+	Commands::Xsend { finish, no_finish, .. } | Commands::Ksend { finish, no_finish, .. } => {
+	    *finish = !*no_finish && (*finish || finish_default);
+	},
+	Commands::Xget { finish, no_finish, overwrite, no_overwrite, .. } => {
+	    *finish = !*no_finish && (*finish || finish_default);
+	    *overwrite = !*no_overwrite && (*overwrite || overwrite_default);
+	},
+	Commands::Kget { overwrite, no_overwrite, .. } => {
+	    *overwrite = !*no_overwrite && (*overwrite || overwrite_default);
+	},
+	Commands::Shell { kermit, xmodem, finish, no_finish }
+	| Commands::Sync { kermit, xmodem, finish, no_finish, .. } => {
+	    *kermit = !*xmodem && (*kermit || kermit_default);
+	    *finish = !*no_finish && (*finish || finish_default);
+	},
+	_ => {},
+    }
+}
+
 fn main() {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+    match config::load(&cli.profile) {
+	Ok(profile) => apply_profile(&mut cli, &profile),
+	Err(e) => helpers::error_handler(format!("Error: {}", e)),
+    }
+    if let Some(path) = &cli.trace {
+	if let Err(e) = trace::start(path) {
+	    helpers::error_handler(format!("Error: {}", e));
+	}
+    }
+    if let Some(path) = &cli.replay {
+	if let Err(e) = replay::start(path) {
+	    helpers::error_handler(format!("Error: {}", e));
+	}
+    }
+    let default_line = config::LineSettings::default();
+    let line = config::LineSettings {
+	data_bits: cli.data_bits.unwrap_or(default_line.data_bits),
+	parity: cli.parity.unwrap_or(default_line.parity),
+	stop_bits: cli.stop_bits.unwrap_or(default_line.stop_bits),
+	flow_control: cli.flow_control.unwrap_or(default_line.flow_control),
+    };
 
     // TODO: in Kermit mode, increase serial timeout
     
     // Dispatch operation
     match &cli.command {
-	Commands::Xsend { direct, path, finish } => {
-	    let mut port = get_serial_port(cli.port, cli.baud);
+	Commands::Xsend { direct, path, more, watch, compile, all_ports, finish, .. } => {
+	    if *all_ports || cli.port.len() > 1 {
+		let ports = get_serial_ports(&cli.port, all_ports, cli.baud, &line);
+		return deploy::deploy(path, ports, |port| match direct {
+		    true => xmodem::send_file_normal(path, port),
+		    false => xmodem::send_file_conn4x(path, port, finish),
+		});
+	    }
+	    let mut port = get_serial_port(one_port(&cli.port), cli.baud, &line, !*direct);
+	    if *watch {
+		let paths: Vec<PathBuf> = std::iter::once(path.clone()).chain(more.iter().cloned()).collect();
+		return watch::watch(&paths, port, &false, compile);
+	    }
 	    //println!("Xsend, direct = {:?}, path = {:?}", direct, path);
 	    // we actually use {:?} on the filename so that it displays in quotes
 	    println!("Sending {:?} {} on {}...",
@@ -183,10 +663,14 @@ fn main() {
 			     " (finish server) used in XModem direct mode.");
 		}
 		// TODO: why do we use different forms of path here versus later?
-		xmodem::send_file_normal(path, &mut port);
+		if let Err(e) = xmodem::send_file_normal(path, &mut port) {
+		    return helpers::error_handler(format!("Error: {}", e));
+		}
 	    } else {
 		// send file to server
-		xmodem::send_file_conn4x(path, &mut port, finish);
+		if let Err(e) = xmodem::send_file_conn4x(path, &mut port, finish) {
+		    return helpers::error_handler(format!("Error: {}", e));
+		}
 	    }
 	    println!("{}", style("Done!").green().bright());
 	    // I like the way this newline and indent looks.
@@ -194,11 +678,14 @@ fn main() {
 	    hp_object::crc_and_output(path);
 	},
 
-	Commands::Xget { direct, path, overwrite, finish } => {
-	    let mut port = get_serial_port(cli.port, cli.baud);
+	Commands::Xget { direct, path, overwrite, finish, .. } => {
+	    let mut port = get_serial_port(one_port(&cli.port), cli.baud, &line, !*direct);
 	    //println!("Xget, path = {:?}, overwrite = {:?}", path, overwrite);
 	    // get the actual path that the transfer wrote to
-	    let final_path = xmodem::get_file(path, &mut port, direct, overwrite, finish);
+	    let final_path = match xmodem::get_file(path, &mut port, direct, overwrite, finish) {
+		Ok(p) => p,
+		Err(e) => return helpers::error_handler(format!("Error: {}", e)),
+	    };
 	    // "of" is not the right preposition to use here, but it
 	    // makes it clear that we're talking about the file after
 	    // processing, stored on the computer's drive.
@@ -206,25 +693,133 @@ fn main() {
 	    hp_object::crc_and_output(&final_path);
 	},
 
-	Commands::Ksend { path, finish } => {
-	    let mut port = get_serial_port(cli.port, cli.baud);
+	Commands::Ksend { path, more, watch, compile, all_ports, finish, .. } => {
+	    if *all_ports || cli.port.len() > 1 {
+		let ports = get_serial_ports(&cli.port, all_ports, cli.baud, &line);
+		return deploy::deploy(path, ports, |port| kermit::send_file(path, port, finish));
+	    }
+	    let mut port = get_serial_port(one_port(&cli.port), cli.baud, &line, false);
+	    if *watch {
+		let paths: Vec<PathBuf> = std::iter::once(path.clone()).chain(more.iter().cloned()).collect();
+		return watch::watch(&paths, port, &true, compile);
+	    }
 	    println!("Sending {:?} via Kermit on {}...",
 		     style(path.file_name().unwrap()).yellow().bright(),
 		     style(port.name().unwrap()).green().bright());
 	    
-	    kermit::send_file(path, &mut port, finish);
+	    if let Err(e) = kermit::send_file(path, &mut port, finish) {
+		return helpers::error_handler(format!("Error: {}", e));
+	    }
 	    print!("File info:\n  ");
 	    hp_object::crc_and_output(path);
 	},
-	Commands::Kget { path, overwrite } => {
-	    let mut port = get_serial_port(cli.port, cli.baud);
-	    let final_path = kermit::get_file(path, &mut port, overwrite);
+	Commands::Kget { path, overwrite, .. } => {
+	    let mut port = get_serial_port(one_port(&cli.port), cli.baud, &line, false);
+	    let final_path = match kermit::get_file(path, &mut port, overwrite) {
+		Ok(p) => p,
+		Err(e) => return helpers::error_handler(format!("Error: {}", e)),
+	    };
 	    print!("Info of received file:\n  ");
 	    hp_object::crc_and_output(&final_path);
 	},
 
-	Commands::Info { path } => {
-	    hp_object::crc_and_output(path);
+	Commands::Info { paths, format, manifest, check, strict } => {
+	    if let Some(manifest_path) = check {
+		manifest::check(manifest_path);
+	    } else if paths.is_empty() {
+		helpers::error_handler("Error: no path given.".to_string());
+	    } else if paths.len() == 1 && paths[0].is_file() && manifest.is_none() {
+		// a single object keeps the plain output
+		hp_object::info_and_output(&paths[0], format, strict);
+	    } else {
+		manifest::info(paths, format, manifest, strict);
+	    }
 	},
+
+	Commands::LibInfo { paths } => {
+	    library::info(paths);
+	},
+
+	Commands::Number { command } => match command {
+	    NumberCommands::Decode { path, exact } => hp_real::decode(path, exact),
+	    NumberCommands::Encode { value, output, extended, overwrite } => {
+		let final_path = hp_real::encode(value, output, extended, overwrite);
+		print!("Info of number:\n  ");
+		hp_object::crc_and_output(&final_path);
+	    },
+	},
+
+	Commands::Data { command } => match command {
+	    DataCommands::Export { path, output, overwrite } => data::export(path, output, overwrite),
+	    DataCommands::Import { path, output, to, extended, overwrite } => {
+		let final_path = data::import(path, output, to, extended, overwrite);
+		print!("Info of object:\n  ");
+		hp_object::crc_and_output(&final_path);
+	    },
+	},
+
+	Commands::Grob { command } => match command {
+	    GrobCommands::Export { path, output, overwrite } => grob::export(path, output, overwrite),
+	    GrobCommands::Import { path, output, overwrite } => {
+		let final_path = grob::import(path, output, overwrite);
+		print!("Info of GROB:\n  ");
+		hp_object::crc_and_output(&final_path);
+	    },
+	},
+
+	Commands::Disasm { path, sysrpl } => disasm::disasm(path, sysrpl),
+
+	Commands::Diff { old, new } => diff::diff(old, new),
+
+	Commands::Dump { path } => dump::dump(path),
+
+	Commands::Fix { path, output, overwrite } => {
+	    if let Some(final_path) = fix::fix(path, output, overwrite) {
+		print!("Info of repaired file:\n  ");
+		hp_object::crc_and_output(&final_path);
+	    }
+	},
+
+	Commands::Shell { kermit, finish, .. } => {
+	    let port = get_serial_port(one_port(&cli.port), cli.baud, &line, true);
+	    shell::run(port, kermit, finish);
+	},
+
+	Commands::Detect { all_bauds } => {
+	    let ports = get_serial_ports(&cli.port, &cli.port.is_empty(), cli.baud, &line);
+	    let bauds: Vec<u32> = match all_bauds {
+		true => detect::COMMON_BAUDS.to_vec(),
+		false => vec![cli.baud.unwrap_or(9600)],
+	    };
+	    detect::detect(ports, &bauds);
+	},
+
+	Commands::Sync { dir, mode, delete, dry_run, kermit, finish, .. } => {
+	    let port = get_serial_port(one_port(&cli.port), cli.baud, &line, true);
+	    sync::sync(dir, port, kermit, mode, delete, dry_run, finish);
+	},
+
+	Commands::Backup { command } => match command {
+	    BackupCommands::List { path } => backup::list(path),
+	    BackupCommands::Extract { path, name, output, overwrite } => {
+		let final_path = backup::extract(path, name, output, overwrite);
+		print!("Info of extracted file:\n  ");
+		hp_object::crc_and_output(&final_path);
+	    },
+	},
+    }
+    replay::finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    // clap only checks the flag definitions when it parses, so a broken
+    // one would otherwise first show up as a panic for users
+    #[test]
+    fn cli() {
+	Cli::command().debug_assert();
     }
 }
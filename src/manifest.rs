@@ -0,0 +1,172 @@
+// Info on many objects at once, and checksum manifests for them. A
+// manifest has one line per object, like sha256sum's output:
+/*
+ * #44ABh DOLIB 3552 games/Arkalite.lib
+ */
+// That is the CRC, the prolog name, the length in nibbles, and the
+// path. The path is last so that it can contain spaces.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+use console::style;
+
+use crate::helpers;
+use crate::hp_object::{self, InfoFormat, ObjectInfo};
+
+// Expand `paths` into a sorted list of files, descending into
+// directories. Files in directories that aren't HP 48 objects are
+// skipped, because an object collection usually has READMEs and
+// other things mixed in.
+fn collect_files(paths: &Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for path in paths {
+	if path.is_dir() {
+	    let mut children: Vec<PathBuf> = match std::fs::read_dir(path) {
+		Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+		Err(e) => {
+		    eprintln!("{}: couldn't read directory {}: {}",
+			      style("warning").yellow().bright(), path.display(), e);
+		    continue;
+		},
+	    };
+	    children.sort();
+	    for child in collect_files(&children) {
+		if child.is_file() && !is_hp_object(&child) {
+		    continue;
+		}
+		files.push(child);
+	    }
+	} else {
+	    files.push(path.to_path_buf());
+	}
+    }
+    return files;
+}
+
+fn is_hp_object(path: &PathBuf) -> bool {
+    let mut header = [0u8; 6];
+    match File::open(path) {
+	Ok(mut f) => std::io::Read::read_exact(&mut f, &mut header).is_ok() && &header == b"HPHP48",
+	Err(_) => false,
+    }
+}
+
+fn manifest_line(path: &PathBuf, info: &ObjectInfo) -> String {
+    format!("{} {} {} {}", hp_object::format_crc(info.crc), info.prolog_name(), info.length, path.display())
+}
+
+// Print info on every object in `paths` (recursing into directories)
+// and optionally write a manifest of them to `manifest`. Exits with an
+// error status if any object couldn't be read, or with `strict`, has
+// trailing data.
+pub fn info(paths: &Vec<PathBuf>, format: &InfoFormat, manifest: &Option<PathBuf>, strict: &bool) {
+    let files = collect_files(paths);
+    let mut failures = 0;
+    let mut manifest_lines: Vec<String> = Vec::new();
+    let mut json_items: Vec<String> = Vec::new();
+
+    if let InfoFormat::Csv = format {
+	println!("path,{}", ObjectInfo::CSV_HEADER);
+    }
+    for path in &files {
+	let info = match hp_object::crc_file(path) {
+	    Ok(i) if *strict => match hp_object::check_trailing(path, &i) {
+		Ok(_) => i,
+		Err(e) => {
+		    eprintln!("{}: {}: {}", style("Error").red().bright(), path.display(), e);
+		    failures += 1;
+		    continue;
+		},
+	    },
+	    Ok(i) => i,
+	    Err(e) => {
+		eprintln!("{}: {}: {}", style("Error").red().bright(), path.display(), e);
+		failures += 1;
+		continue;
+	    },
+	};
+	match format {
+	    InfoFormat::Text => println!("{}: {}", style(path.display()).yellow().bright(), info),
+	    // add the path as the first field of the object
+	    InfoFormat::Json => json_items.push(format!("{{\"path\": {}, {}",
+							helpers::json_string(&path.display().to_string()),
+							&info.to_json()[1..])),
+	    InfoFormat::Csv => println!("{},{}", helpers::csv_field(&path.display().to_string()), info.to_csv()),
+	}
+	manifest_lines.push(manifest_line(path, &info));
+    }
+    if let InfoFormat::Json = format {
+	println!("[{}]", json_items.join(",\n "));
+    }
+
+    if let Some(manifest_path) = manifest {
+	let result = File::create(manifest_path).and_then(|mut f| {
+	    for line in &manifest_lines {
+		writeln!(f, "{}", line)?;
+	    }
+	    Ok(())
+	});
+	if let Err(e) = result {
+	    crate::helpers::error_handler(format!("Error: couldn't write manifest {}: {}",
+						  manifest_path.display(), e));
+	}
+    }
+
+    if failures != 0 {
+	crate::helpers::error_handler(format!("{} of {} objects could not be read", failures, files.len()));
+    }
+}
+
+// Re-check every object listed in `manifest`, like `sha256sum -c`.
+pub fn check(manifest: &PathBuf) {
+    let contents = match std::fs::read_to_string(manifest) {
+	Ok(c) => c,
+	Err(e) => return crate::helpers::error_handler(
+	    format!("Error: couldn't read manifest {}: {}", manifest.display(), e)),
+    };
+
+    let mut failures = 0;
+    let mut count = 0;
+    for (line_number, line) in contents.lines().enumerate() {
+	if line.trim().is_empty() {
+	    continue;
+	}
+	let fields: Vec<&str> = line.splitn(4, ' ').collect();
+	if fields.len() != 4 {
+	    eprintln!("{}: {}: line {} is not a manifest entry",
+		      style("warning").yellow().bright(), manifest.display(), line_number + 1);
+	    continue;
+	}
+	let (crc, prolog_name, length, path) = (fields[0], fields[1], fields[2], PathBuf::from(fields[3]));
+	count += 1;
+
+	let problem = match hp_object::crc_file(&path) {
+	    Err(e) => Some(e),
+	    Ok(info) => {
+		if hp_object::format_crc(info.crc) != crc {
+		    Some(format!("CRC is {}, expected {}", hp_object::format_crc(info.crc), crc))
+		} else if info.length.to_string() != length {
+		    Some(format!("length is {} nibbles, expected {}", info.length, length))
+		} else if info.prolog_name() != prolog_name {
+		    Some(format!("type is {}, expected {}", info.prolog_name(), prolog_name))
+		} else {
+		    None
+		}
+	    },
+	};
+	match problem {
+	    None => println!("{}: {}", path.display(), style("OK").green().bright()),
+	    Some(p) => {
+		println!("{}: {} ({})", path.display(), style("FAILED").red().bright(), p);
+		failures += 1;
+	    },
+	}
+    }
+
+    if failures != 0 {
+	crate::helpers::error_handler(format!("{}: {} of {} objects did NOT match",
+					      style("warning").yellow().bright(), failures, count));
+    }
+}
@@ -0,0 +1,398 @@
+// --replay FILE: instead of opening real serial ports, play back a
+// capture made with --trace. What the calculator sent is handed to
+// the XModem and Kermit code as it was recorded, and everything Alum
+// writes has to match what it wrote back then, byte for byte. On the
+// first difference, Alum stops and prints both packets:
+/*
+ * $ alum --replay xsend.trace xsend prog.hp
+ * Sending "prog.hp" to XModem server on /dev/ttyUSB0...
+ * Error: replay of /dev/ttyUSB0 differs at byte 23 of write 2
+ *   expected (from the capture):
+ *     XModem block 1 (128 bytes), CRC D473 ok
+ *     0000: 01 01 fe 48 50 48 50 34 38 2d 52 9d 2d c0 dc 02  |...HPHP48-R.-...|
+ *     0010: 41 00 80 bf 79 06 41 13 47 08 c8 43 21 43 85 8a  |A...y.A.G..C!C..|
+ *     ...
+ *   actual (from Alum):
+ *     XModem block 1 (128 bytes), CRC B231 ok
+ *     ...
+ */
+// So a trace of a problem on real hardware, like the x48 timing
+// trouble, can be run again after every change, without the
+// calculator. Alum exits with 1 on a difference or when it didn't
+// write everything in the capture, so replays work in scripts.
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+use console::style;
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+enum Event {
+    // bytes Alum wrote in one write()
+    Write(Vec<u8>),
+    // bytes one read() returned
+    Read(Vec<u8>),
+    // a read() that timed out
+    Timeout,
+}
+
+// Everything that happened on one port, in order. Each time a port
+// is opened again, it carries on from where the last one stopped, like
+// the trace did.
+struct Session {
+    name: String,
+    events: VecDeque<Event>,
+    // how far into the first event we are
+    pos: usize,
+    writes: usize,
+    opened: bool,
+}
+
+static SESSIONS: OnceLock<Mutex<Vec<Session>>> = OnceLock::new();
+
+// Read the capture at `path`. From now on, opening a port gives its
+// recorded session.
+pub fn start(path: &PathBuf) -> Result<(), String> {
+    let text = match std::fs::read_to_string(path) {
+	Ok(t) => t,
+	Err(e) => return Err(format!("couldn't read capture {}: {}", path.display(), e)),
+    };
+    let sessions = match parse(&text) {
+	Ok(s) => s,
+	Err(e) => return Err(format!("{}:{}", path.display(), e)),
+    };
+    if sessions.is_empty() {
+	return Err(format!("{} has no serial traffic in it", path.display()));
+    }
+    let _ = SESSIONS.set(Mutex::new(sessions));
+    return Ok(());
+}
+
+// The sessions in the trace `text`. Errors start with the line number.
+fn parse(text: &str) -> Result<Vec<Session>, String> {
+    let mut sessions: Vec<Session> = Vec::new();
+    // lines of one read() or write() have the same call number, or in
+    // traces from before there were call numbers, the same time
+    let mut last_key: Option<(String, String, String)> = None;
+    for (number, line) in text.lines().enumerate() {
+	if line.is_empty() || line.starts_with('#') {
+	    continue;
+	}
+	let fields: Vec<&str> = line.splitn(4, ' ').collect();
+	if fields.len() < 4 {
+	    return Err(format!("{}: not a trace line", number + 1));
+	}
+	let (time, name, direction, mut rest) = (fields[0], fields[1], fields[2], fields[3]);
+	let mut call = time;
+	if let Some((count, hex)) = rest.split_once(": ") {
+	    if count.parse::<u64>().is_ok() {
+		call = count;
+		rest = hex;
+	    }
+	}
+	let index = match sessions.iter().position(|s| s.name == name) {
+	    Some(i) => i,
+	    None => {
+		sessions.push(Session { name: name.to_owned(), events: VecDeque::new(), pos: 0, writes: 0, opened: false });
+		sessions.len() - 1
+	    },
+	};
+	let key = (call.to_owned(), name.to_owned(), direction.to_owned());
+	let same_call = last_key.as_ref() == Some(&key);
+	last_key = Some(key);
+	let events = &mut sessions[index].events;
+	match direction {
+	    ">" | "<" => {
+		// the hex is before the |ASCII| column
+		let mut bytes = Vec::new();
+		for hex in rest.split('|').next().unwrap_or("").split_whitespace() {
+		    match u8::from_str_radix(hex, 16) {
+			Ok(b) => bytes.push(b),
+			Err(_) => return Err(format!("{}: bad byte {:?}", number + 1, hex)),
+		    }
+		}
+		match (events.back_mut(), direction) {
+		    (Some(Event::Write(b)), ">") | (Some(Event::Read(b)), "<") if same_call => b.extend(bytes),
+		    (_, ">") => events.push_back(Event::Write(bytes)),
+		    _ => events.push_back(Event::Read(bytes)),
+		}
+	    },
+	    "#" => if rest.starts_with("read failed") {
+		events.push_back(Event::Timeout);
+	    },
+	    _ => return Err(format!("{}: unknown direction {:?}", number + 1, direction)),
+	}
+    }
+    return Ok(sessions);
+}
+
+// A mismatch panics in a deploy thread while holding the lock, which
+// shouldn't take the other ports down with it.
+fn sessions() -> MutexGuard<'static, Vec<Session>> {
+    return SESSIONS.get().unwrap().lock().unwrap_or_else(|e| e.into_inner());
+}
+
+pub fn is_replaying() -> bool {
+    return SESSIONS.get().is_some();
+}
+
+// The ports in the capture, for when no --port is given.
+pub fn ports() -> Vec<String> {
+    if !is_replaying() {
+	return Vec::new();
+    }
+    return sessions().iter().map(|s| s.name.clone()).collect();
+}
+
+// "Open" `name`, which has to be in the capture.
+pub fn open(name: &str, baud: u32, timeout: Duration) -> Result<Box<dyn SerialPort>, String> {
+    let mut sessions = sessions();
+    return match sessions.iter_mut().find(|s| s.name == name) {
+	Some(session) => {
+	    session.opened = true;
+	    Ok(Box::new(ReplayPort { name: name.to_owned(), baud, timeout }))
+	},
+	None => Err(format!("Error: failed to open port: {} isn't in the capture", name)),
+    };
+}
+
+// Check that Alum wrote everything in the capture. Called after the
+// command finished.
+pub fn finish() {
+    if !is_replaying() {
+	return;
+    }
+    let sessions = sessions();
+    let mut problems = Vec::new();
+    for session in sessions.iter() {
+	let missing: usize = session.events.iter().enumerate().map(|(i, event)| match event {
+	    Event::Write(b) if i == 0 => b.len() - session.pos,
+	    Event::Write(b) => b.len(),
+	    _ => 0,
+	}).sum();
+	if !session.opened {
+	    problems.push(format!("{} was never opened", session.name));
+	} else if missing > 0 {
+	    problems.push(format!("{} stopped after write {}, with {} more bytes to write in the capture",
+				  session.name, session.writes, missing));
+	}
+    }
+    if !problems.is_empty() {
+	crate::helpers::error_handler(format!("Error: replay ended early: {}", problems.join(", ")));
+    }
+    for session in sessions.iter() {
+	println!("Replay of {} matched ({} writes)", style(&session.name).green().bright(), session.writes);
+    }
+}
+
+// Stop with both sides of the first difference, as packets and as
+// the hex around it.
+fn mismatch(session: &Session, offset: usize, expected: &[u8], actual: &[u8], note: &str) {
+    let mut message = format!("Error: replay of {} differs at byte {} of write {}",
+			      session.name, offset, session.writes + 1);
+    if !note.is_empty() {
+	message += &format!(" ({})", note);
+    }
+    for (label, bytes) in [("expected (from the capture)", expected), ("actual (from Alum)", actual)] {
+	message += &format!("\n  {}:", label);
+	if bytes.is_empty() {
+	    message += "\n    nothing";
+	    continue;
+	}
+	let (_, summaries) = crate::trace::describe_packets(bytes, false);
+	for summary in summaries.iter().take(4) {
+	    message += &format!("\n    {}", summary);
+	}
+	if summaries.len() > 4 {
+	    message += &format!("\n    ...and {} more", summaries.len() - 4);
+	}
+	let row = offset / 16;
+	for (i, line) in crate::trace::hex_rows(bytes).iter().enumerate().skip(row.saturating_sub(1)).take(3) {
+	    message += &format!("\n    {:04x}: {}", i * 16, line);
+	}
+    }
+    crate::helpers::error_handler(message);
+}
+
+struct ReplayPort {
+    name: String,
+    baud: u32,
+    timeout: Duration,
+}
+
+impl ReplayPort {
+    fn with_session<T>(&self, f: impl FnOnce(&mut Session) -> T) -> T {
+	let mut sessions = sessions();
+	let session = sessions.iter_mut().find(|s| s.name == self.name).unwrap();
+	return f(session);
+    }
+}
+
+fn timed_out(why: &str) -> std::io::Error {
+    return std::io::Error::new(std::io::ErrorKind::TimedOut, why.to_owned());
+}
+
+impl Read for ReplayPort {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+	let result = self.with_session(|session| {
+	    match session.events.front() {
+		Some(Event::Read(data)) => {
+		    let n = buf.len().min(data.len() - session.pos);
+		    buf[..n].copy_from_slice(&data[session.pos..session.pos + n]);
+		    session.pos += n;
+		    if session.pos == data.len() {
+			session.events.pop_front();
+			session.pos = 0;
+		    }
+		    Some(Ok(n))
+		},
+		Some(Event::Timeout) => {
+		    session.events.pop_front();
+		    Some(Err(timed_out("Operation timed out")))
+		},
+		_ => None,
+	    }
+	});
+	return match result {
+	    Some(r) => r,
+	    // the calculator was waiting for us, so wait like a real port
+	    // would (without holding up the other ports)
+	    None => {
+		std::thread::sleep(self.timeout);
+		Err(timed_out("Operation timed out (nothing to read in the capture)"))
+	    },
+	};
+    }
+}
+
+impl Write for ReplayPort {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+	self.with_session(|session| {
+	    let mut done = 0;
+	    while done < buf.len() {
+		let data = match session.events.front() {
+		    Some(Event::Write(data)) => data,
+		    Some(Event::Read(data)) => {
+			let (_, summaries) = crate::trace::describe_packets(&data[session.pos..], false);
+			let note = format!("the capture has the calculator sending next: {}", summaries.join(", "));
+			return mismatch(session, 0, &[], &buf[done..], &note);
+		    },
+		    Some(Event::Timeout) => {
+			return mismatch(session, 0, &[], &buf[done..], "the capture has Alum waiting for an answer next");
+		    },
+		    None => return mismatch(session, 0, &[], &buf[done..], "the capture ends here"),
+		};
+		let expected = &data[session.pos..];
+		let n = expected.len().min(buf.len() - done);
+		if let Some(i) = (0..n).find(|i| expected[*i] != buf[done + i]) {
+		    let expected = expected.to_vec();
+		    return mismatch(session, i, &expected, &buf[done..], "");
+		}
+		done += n;
+		session.pos += n;
+		if session.pos == data.len() {
+		    session.events.pop_front();
+		    session.pos = 0;
+		}
+	    }
+	    session.writes += 1;
+	});
+	return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+	return Ok(());
+    }
+}
+
+// The settings don't matter, but the code reads some of them back.
+impl SerialPort for ReplayPort {
+    fn name(&self) -> Option<String> { Some(self.name.clone()) }
+    fn baud_rate(&self) -> serialport::Result<u32> { Ok(self.baud) }
+    fn data_bits(&self) -> serialport::Result<DataBits> { Ok(DataBits::Eight) }
+    fn flow_control(&self) -> serialport::Result<FlowControl> { Ok(FlowControl::None) }
+    fn parity(&self) -> serialport::Result<Parity> { Ok(Parity::None) }
+    fn stop_bits(&self) -> serialport::Result<StopBits> { Ok(StopBits::One) }
+    fn timeout(&self) -> Duration { self.timeout }
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+	self.baud = baud_rate;
+	Ok(())
+    }
+    fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> { Ok(()) }
+    fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> { Ok(()) }
+    fn set_parity(&mut self, _: Parity) -> serialport::Result<()> { Ok(()) }
+    fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> { Ok(()) }
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+	self.timeout = timeout;
+	Ok(())
+    }
+    fn write_request_to_send(&mut self, _: bool) -> serialport::Result<()> { Ok(()) }
+    fn write_data_terminal_ready(&mut self, _: bool) -> serialport::Result<()> { Ok(()) }
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> { Ok(true) }
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> { Ok(true) }
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> { Ok(false) }
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> { Ok(true) }
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+	return Ok(self.with_session(|session| match session.events.front() {
+	    Some(Event::Read(data)) => (data.len() - session.pos) as u32,
+	    _ => 0,
+	}));
+    }
+    fn bytes_to_write(&self) -> serialport::Result<u32> { Ok(0) }
+    // bytes that were thrown away never made it into the trace, so
+    // there's nothing to clear
+    fn clear(&self, _: ClearBuffer) -> serialport::Result<()> { Ok(()) }
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+	Ok(Box::new(ReplayPort { name: self.name.clone(), baud: self.baud, timeout: self.timeout }))
+    }
+    fn set_break(&self) -> serialport::Result<()> { Ok(()) }
+    fn clear_break(&self) -> serialport::Result<()> { Ok(()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn writes(session: &Session) -> Vec<Vec<u8>> {
+	return session.events.iter().filter_map(|e| match e {
+	    Event::Write(b) => Some(b.clone()),
+	    _ => None,
+	}).collect();
+    }
+
+    #[test]
+    fn calls_in_the_same_microsecond_stay_apart() {
+	let text = "0.000100 /dev/ttyUSB0 > 1: 51  |Q|\n\
+		    0.000100 /dev/ttyUSB0 > 2: 51  |Q|\n";
+	let sessions = parse(text).unwrap();
+	assert_eq!(writes(&sessions[0]), vec![vec![0x51], vec![0x51]]);
+    }
+
+    #[test]
+    fn rows_of_one_call_are_joined() {
+	let text = "0.000100 /dev/ttyUSB0 > 1: 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f  |................|\n\
+		    0.000100 /dev/ttyUSB0 > 1: 10  |.|\n\
+		    0.000200 /dev/ttyUSB0 # a comment\n\
+		    0.000300 /dev/ttyUSB0 < 2: 06  |.|\n";
+	let sessions = parse(text).unwrap();
+	assert_eq!(writes(&sessions[0]), vec![(0..=0x10).collect::<Vec<u8>>()]);
+	assert_eq!(sessions[0].events.len(), 2);
+    }
+
+    #[test]
+    fn old_traces_join_rows_by_time() {
+	let text = "0.000100 /dev/ttyUSB0 > 50 00  |P.|\n\
+		    0.000100 /dev/ttyUSB0 > 07  |.|\n\
+		    0.000200 /dev/ttyUSB0 > 07  |.|\n";
+	let sessions = parse(text).unwrap();
+	assert_eq!(writes(&sessions[0]), vec![vec![0x50, 0, 7], vec![7]]);
+    }
+
+    #[test]
+    fn bad_bytes_give_the_line_number() {
+	assert_eq!(parse("# header\n0.1 p > 1: zz\n").err(), Some("2: bad byte \"zz\"".to_owned()));
+    }
+}
@@ -0,0 +1,45 @@
+// Reading from the serial port without guessing how long the
+// calculator takes. Instead of sleeping and hoping a whole packet has
+// arrived, keep reading until we have as many bytes as the protocol
+// says, or until a deadline passes. A packet can come in any number
+// of pieces, which is what happens with fast links and USB adapters.
+
+use std::io::ErrorKind;
+use std::time::Instant;
+
+// When a read that starts now should give up: the port's timeout from
+// now (--timeout in the config file, or less for detect's probes).
+pub fn deadline(port: &Box<dyn serialport::SerialPort>) -> Instant {
+    return Instant::now() + port.timeout();
+}
+
+// Read until `buf` holds at least `n` bytes. Whatever is already in
+// `buf` counts, so the packet readers can look at a few bytes, throw
+// some away to resynchronize, and ask for more.
+pub fn fill(port: &mut Box<dyn serialport::SerialPort>, buf: &mut Vec<u8>, n: usize, deadline: Instant)
+	    -> Result<(), String> {
+    let timeout = port.timeout();
+    let mut chunk = [0u8; 1030];
+    let mut result = Ok(());
+    while buf.len() < n {
+	let now = Instant::now();
+	if now >= deadline {
+	    result = Err(format!("timed out after {} of {} bytes", buf.len(), n));
+	    break;
+	}
+	// don't wait past the deadline in one read
+	let _ = port.set_timeout((deadline - now).min(timeout));
+	let wanted = (n - buf.len()).min(chunk.len());
+	match port.read(&mut chunk[..wanted]) {
+	    Ok(count) => buf.extend_from_slice(&chunk[..count]),
+	    Err(e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::Interrupted => {},
+	    Err(e) => {
+		result = Err(e.to_string());
+		break;
+	    },
+	}
+    }
+    let _ = port.set_timeout(timeout);
+    return result;
+}
+
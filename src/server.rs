@@ -0,0 +1,140 @@
+// A connection to the XModem or Kermit server that stays open for
+// several operations, for `alum shell` and `alum sync`. Everything is
+// done with the server's get, put, and execute commands. To find out
+// what's in the current directory, we have the calculator store a
+// list in a temporary variable, get it, and purge it.
+
+use std::path::PathBuf;
+
+use crate::hp_object;
+use crate::kermit;
+use crate::xmodem;
+
+// name of the temporary variable that holds listings
+const LIST_VAR: &str = "ALUMLS";
+
+pub struct Server {
+    pub port: Box<dyn serialport::SerialPort>,
+    pub kermit: bool,
+}
+
+// What list_crcs() found in the current directory.
+pub struct Listing {
+    // names and CRCs of the variables that aren't directories
+    pub variables: Vec<(String, u32)>,
+    pub directories: Vec<String>,
+}
+
+// The variable that put() stores `path` in. The calculator drops the
+// extension, so GAME.hp becomes GAME.
+pub fn variable_name(path: &PathBuf) -> String {
+    return path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+}
+
+// The elements of the list object in `nibs`.
+fn list_elements(nibs: &[u8]) -> Result<Vec<&[u8]>, String> {
+    if hp_object::get_prolog(nibs)? != 0x2a74 {
+	return Err("calculator did not send a list".to_owned());
+    }
+    let elements = hp_object::composite_elements(nibs)?;
+    return Ok(elements.iter().map(|(start, len)| &nibs[*start..*start + *len]).collect());
+}
+
+// Names from a list of identifiers: prolog, then ASCIC name.
+fn identifier_names(list: &[u8]) -> Result<Vec<String>, String> {
+    let mut names: Vec<String> = Vec::new();
+    for id in list_elements(list)? {
+	let len = hp_object::read_nibbles(id, 5, 2)? as usize;
+	names.push(hp_object::read_hp_string(id, 7, len)?);
+    }
+    return Ok(names);
+}
+
+impl Server {
+    pub fn get(&mut self, name: &str) -> Result<Vec<u8>, String> {
+	match self.kermit {
+	    true => kermit::get_data(name, &mut self.port),
+	    false => xmodem::get_data(name, &mut self.port),
+	}
+    }
+
+    pub fn execute(&mut self, command: &str) -> Result<(), String> {
+	match self.kermit {
+	    true => kermit::execute(command, &mut self.port),
+	    false => xmodem::execute(command, &mut self.port),
+	}
+    }
+
+    pub fn put(&mut self, path: &PathBuf) -> Result<(), String> {
+	match self.kermit {
+	    true => kermit::send_file(path, &mut self.port, &false),
+	    false => xmodem::send_file_conn4x(path, &mut self.port, &false),
+	}
+    }
+
+    pub fn finish(&mut self) -> Result<(), String> {
+	match self.kermit {
+	    true => kermit::finish_server(&mut self.port),
+	    false => xmodem::finish_server(&mut self.port),
+	}
+    }
+
+    // Purge a variable. Directories are left alone, because PURGE
+    // only removes them if they're empty.
+    pub fn purge(&mut self, name: &str) -> Result<(), String> {
+	return self.execute(&format!("IF '{0}' VTYPE 15 == NOT THEN '{0}' PURGE END", name));
+    }
+
+    // Purge a directory and everything in it.
+    pub fn purge_dir(&mut self, name: &str) -> Result<(), String> {
+	return self.execute(&format!("IF '{0}' VTYPE 15 == THEN '{0}' PGDIR END", name));
+    }
+
+    // Run `program`, which leaves one object on the stack, and get
+    // that object.
+    fn evaluate(&mut self, program: &str) -> Result<Vec<u8>, String> {
+	self.execute(&format!("{} '{}' STO", program, LIST_VAR))?;
+	let contents = self.get(LIST_VAR)?;
+	self.execute(&format!("'{}' PURGE", LIST_VAR))?;
+	let (_, mut nibs) = hp_object::bytes_to_nibbles(&contents)?;
+	// XModem trims 0x00 bytes from the end, and a list ends with
+	// the 0 nibble of SEMI, so put back what might be missing
+	nibs.resize(nibs.len() + 4, 0);
+	return Ok(nibs);
+    }
+
+    // Names of the variables in the current directory.
+    pub fn list(&mut self) -> Result<Vec<String>, String> {
+	let nibs = self.evaluate("VARS")?;
+	return identifier_names(&nibs);
+    }
+
+    // Names and CRCs (from BYTES) of the variables in the current
+    // directory, and which of them are directories (15 TVARS).
+    // Definite loops always run once, so an empty directory is
+    // checked first.
+    pub fn list_crcs(&mut self) -> Result<Listing, String> {
+	let nibs = self.evaluate(
+	    "« VARS { } IF OVER SIZE THEN 1 3 PICK SIZE FOR i OVER i GET RCL BYTES DROP + NEXT END \
+	     15 TVARS 3 →LIST » EVAL")?;
+	let parts = list_elements(&nibs)?;
+	if parts.len() != 3 {
+	    return Err("calculator sent a bad listing".to_owned());
+	}
+	let names = identifier_names(parts[0])?;
+	let directories = identifier_names(parts[2])?;
+	let mut crcs: Vec<u32> = Vec::new();
+	for hxs in list_elements(parts[1])? {
+	    if hp_object::get_prolog(hxs)? != 0x2a4e {
+		return Err("BYTES did not return a binary integer".to_owned());
+	    }
+	    // the CRC is the low 4 nibbles of the 16-nibble value
+	    crcs.push(hp_object::read_nibbles(hxs, 10, 4)?);
+	}
+	if names.len() != crcs.len() {
+	    return Err("calculator sent a bad listing".to_owned());
+	}
+	let variables = names.into_iter().zip(crcs).filter(|(name, _)| !directories.contains(name)).collect();
+	return Ok(Listing { variables, directories });
+    }
+}
@@ -0,0 +1,218 @@
+// `alum shell`: an interactive session with the XModem or Kermit
+// server on one open port. The subcommands in main.rs open the port
+// and start over for every file, which is slow when working with the
+// calculator by hand.
+
+use std::path::PathBuf;
+
+use console::style;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use crate::hp_object;
+use crate::server::Server;
+
+const COMMANDS: [(&str, &str); 11] = [
+    ("ls", "list variables in the current directory"),
+    ("cd", "change directory: cd NAME, cd .. (UPDIR), or cd / (HOME)"),
+    ("get", "get variable from calculator: get NAME [FILE]"),
+    ("put", "send file to calculator: put FILE"),
+    ("rm", "purge variable on calculator: rm NAME"),
+    ("rmdir", "purge directory and everything in it on calculator: rmdir NAME"),
+    ("exec", "run command line on calculator: exec 1 2 +"),
+    ("info", "show size and checksum of variable on calculator: info NAME"),
+    ("help", "show this help"),
+    ("quit", "leave the shell"),
+    ("exit", "leave the shell"),
+];
+
+struct ShellHelper {
+    // variables in the current calculator directory, from the last `ls`
+    names: Vec<String>,
+    files: FilenameCompleter,
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+	let before = &line[..pos];
+	let word_start = match before.rfind(' ') {
+	    Some(i) => i + 1,
+	    None => 0,
+	};
+	let word = &before[word_start..];
+	let candidates: Vec<&str> = if word_start == 0 {
+	    COMMANDS.iter().map(|(c, _)| *c).collect()
+	} else {
+	    match before.split_whitespace().next() {
+		// local files are sent, not calculator variables
+		Some("put") => return self.files.complete(line, pos, ctx),
+		Some("get") | Some("cd") | Some("rm") | Some("rmdir") | Some("info") | Some("exec") =>
+		    self.names.iter().map(|n| n.as_str()).collect(),
+		_ => Vec::new(),
+	    }
+	};
+	let pairs = candidates.iter()
+	    .filter(|c| c.starts_with(word))
+	    .map(|c| Pair { display: c.to_string(), replacement: c.to_string() })
+	    .collect();
+	return Ok((word_start, pairs));
+    }
+}
+
+// rustyline needs all of these for a Helper, but we only complete
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+impl Highlighter for ShellHelper {}
+impl Validator for ShellHelper {}
+impl Helper for ShellHelper {}
+
+fn print_help() {
+    for (command, description) in COMMANDS {
+	println!("  {:<6}{}", style(command).yellow().bright(), description);
+    }
+}
+
+// Run the shell on `port` until quit or end of input. `finish` quits
+// the server on the way out.
+pub fn run(port: Box<dyn serialport::SerialPort>, kermit: &bool, finish: &bool) {
+    let mut session = Server { port, kermit: *kermit };
+    let mut editor: Editor<ShellHelper, DefaultHistory> = match Editor::new() {
+	Ok(e) => e,
+	Err(e) => return crate::helpers::error_handler(format!("Error: couldn't start line editor: {}", e)),
+    };
+    editor.set_helper(Some(ShellHelper { names: Vec::new(), files: FilenameCompleter::new() }));
+
+    println!("Connected to {} server on {}. Type {} for commands.",
+	     if *kermit { "Kermit" } else { "XModem" },
+	     style(session.port.name().unwrap_or_default()).green().bright(),
+	     style("help").yellow().bright());
+
+    // get names for completion right away
+    match session.list() {
+	Ok(names) => editor.helper_mut().unwrap().names = names,
+	Err(e) => eprintln!("{}: couldn't list variables: {}", style("warning").yellow().bright(), e),
+    }
+
+    loop {
+	let line = match editor.readline("alum> ") {
+	    Ok(l) => l,
+	    Err(ReadlineError::Interrupted) => continue,
+	    Err(ReadlineError::Eof) => break,
+	    Err(e) => return crate::helpers::error_handler(format!("Error: {}", e)),
+	};
+	let line = line.trim();
+	if line.is_empty() {
+	    continue;
+	}
+	let _ = editor.add_history_entry(line);
+
+	let (command, arg) = match line.split_once(' ') {
+	    Some((c, a)) => (c, a.trim()),
+	    None => (line, ""),
+	};
+	let needs_arg = ["cd", "get", "put", "rm", "rmdir", "exec", "info"];
+	if needs_arg.contains(&command) && arg.is_empty() {
+	    eprintln!("{}: {} needs an argument", style("error").red().bright(), command);
+	    continue;
+	}
+
+	let mut refresh = false;
+	let result = match command {
+	    "quit" | "exit" => break,
+	    "help" => Ok(print_help()),
+	    "ls" => session.list().map(|names| {
+		for name in &names {
+		    println!("{}", name);
+		}
+		editor.helper_mut().unwrap().names = names;
+	    }),
+	    "cd" => {
+		refresh = true;
+		match arg {
+		    ".." => session.execute("UPDIR"),
+		    "/" => session.execute("HOME"),
+		    // only evaluate the name if it is a directory
+		    _ => session.execute(&format!("IF '{0}' VTYPE 15 == THEN {0} END", arg)),
+		}
+	    },
+	    "get" => {
+		// the local name can be given after the variable name
+		let (name, local) = match arg.split_once(' ') {
+		    Some((n, l)) => (n, PathBuf::from(l.trim())),
+		    None => (arg, PathBuf::from(arg)),
+		};
+		get(&mut session, name, local)
+	    },
+	    "put" => {
+		let path = PathBuf::from(arg);
+		if !path.is_file() {
+		    eprintln!("{}: no such file {}", style("error").red().bright(), path.display());
+		    continue;
+		}
+		refresh = true;
+		session.put(&path).map(|_| {
+		    print!("Sent: ");
+		    hp_object::crc_and_output(&path);
+		})
+	    },
+	    "rm" => {
+		refresh = true;
+		session.purge(arg)
+	    },
+	    "rmdir" => {
+		refresh = true;
+		session.purge_dir(arg)
+	    },
+	    "exec" => session.execute(arg),
+	    "info" => session.get(arg)
+		.and_then(|contents| hp_object::bytes_to_nibbles(&contents))
+		.and_then(|(romrev, nibs)| hp_object::object_info(romrev, &nibs))
+		.map(|info| println!("{}", info)),
+	    _ => Err(format!("unknown command {:?} (try {})", command, style("help").yellow().bright())),
+	};
+	// the server is still there after an error, so keep going
+	if let Err(e) = result {
+	    eprintln!("{}: {}", style("error").red().bright(), e);
+	}
+
+	// keep completion up to date after anything that changes the directory
+	if refresh {
+	    match session.list() {
+		Ok(names) => editor.helper_mut().unwrap().names = names,
+		Err(e) => eprintln!("{}: couldn't list variables: {}", style("warning").yellow().bright(), e),
+	    }
+	}
+    }
+
+    if *finish {
+	if let Err(e) = session.finish() {
+	    crate::helpers::error_handler(format!("Error: {}", e));
+	}
+    }
+}
+
+// Get `name` into the file `local`, or a new name next to it if it
+// exists. Nothing is written if the calculator sends nothing, which
+// is what happens when there's no such variable.
+fn get(session: &mut Server, name: &str, local: PathBuf) -> Result<(), String> {
+    let contents = session.get(name)?;
+    if contents.is_empty() {
+	return Err(format!("calculator sent nothing for {}; is there a variable by that name?", name));
+    }
+    let final_path = crate::helpers::get_unique_path(local);
+    if let Err(e) = std::fs::write(&final_path, &contents) {
+	return Err(format!("couldn't write {}: {}", final_path.display(), e));
+    }
+    println!("Got {} as {}", style(name).yellow().bright(), style(final_path.display()).yellow().bright());
+    print!("  ");
+    hp_object::crc_and_output(&final_path);
+    return Ok(());
+}
@@ -0,0 +1,179 @@
+// `alum sync`: make a folder on the computer and the current
+// directory on the calculator match. Variables are matched to files
+// by name (without the extension) and compared by CRC, so only what
+// differs is transferred. The CRCs on the calculator side come from
+// BYTES, which uses the same algorithm as `alum info`. Nothing is
+// deleted without --delete, and directories are never touched.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use console::style;
+
+use crate::hp_object;
+use crate::server::{self, Server};
+
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq)]
+pub enum SyncMode {
+    // make the calculator match the folder
+    Push,
+    // make the folder match the calculator
+    Pull,
+    // copy what's missing on either side, delete nothing
+    Both,
+}
+
+enum Action {
+    // file name
+    Upload(String),
+    // variable name and file name
+    Download(String, String),
+    // file name
+    DeleteLocal(String),
+    // variable name
+    DeleteCalc(String),
+    // changed on both sides in `both` mode, so we don't know which wins
+    Conflict(String),
+    // only on one side, and --delete wasn't given: file or variable name
+    KeepLocal(String),
+    KeepCalc(String),
+}
+
+// HP objects directly in `dir`, by the name of the variable they are
+// sent to, with their file names and CRCs. Subfolders aren't synced.
+fn local_objects(dir: &PathBuf) -> Result<HashMap<String, (String, u32)>, String> {
+    let entries = match std::fs::read_dir(dir) {
+	Ok(e) => e,
+	Err(e) => return Err(format!("couldn't read directory {}: {}", dir.display(), e)),
+    };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_file()).collect();
+    paths.sort();
+    let mut objects: HashMap<String, (String, u32)> = HashMap::new();
+    for path in paths {
+	// other files (READMEs and such) aren't part of the sync
+	let info = match hp_object::crc_file(&path) {
+	    Ok(i) => i,
+	    Err(_) => continue,
+	};
+	let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+	let name = server::variable_name(&path);
+	if let Some((other, _)) = objects.get(&name) {
+	    eprintln!("{}: {} and {} would both be {} on the calculator; skipping {}",
+		      style("warning").yellow().bright(), other, file_name, name, file_name);
+	    continue;
+	}
+	objects.insert(name, (file_name, info.crc));
+    }
+    return Ok(objects);
+}
+
+fn plan(local: &HashMap<String, (String, u32)>, calc: &Vec<(String, u32)>, mode: &SyncMode, delete: bool)
+	-> Vec<Action> {
+    let mut actions: Vec<Action> = Vec::new();
+    for (name, crc) in calc {
+	match (local.get(name), mode) {
+	    (Some((_, c)), _) if c == crc => {},
+	    (Some((file, _)), SyncMode::Push) => actions.push(Action::Upload(file.clone())),
+	    (Some((file, _)), SyncMode::Pull) => actions.push(Action::Download(name.clone(), file.clone())),
+	    (Some(_), SyncMode::Both) => actions.push(Action::Conflict(name.clone())),
+	    (None, SyncMode::Push) if delete => actions.push(Action::DeleteCalc(name.clone())),
+	    (None, SyncMode::Push) => actions.push(Action::KeepCalc(name.clone())),
+	    (None, _) => actions.push(Action::Download(name.clone(), name.clone())),
+	}
+    }
+    let mut local_only: Vec<&String> = local.keys().filter(|n| !calc.iter().any(|(c, _)| c == *n)).collect();
+    local_only.sort();
+    for name in local_only {
+	let file = local[name].0.clone();
+	match mode {
+	    SyncMode::Pull if delete => actions.push(Action::DeleteLocal(file)),
+	    SyncMode::Pull => actions.push(Action::KeepLocal(file)),
+	    _ => actions.push(Action::Upload(file)),
+	}
+    }
+    return actions;
+}
+
+fn describe(action: &Action) -> String {
+    match action {
+	Action::Upload(f) => format!("{} {}", style("upload").green().bright(), f),
+	Action::Download(n, f) if n == f => format!("{} {}", style("download").green().bright(), n),
+	Action::Download(n, f) => format!("{} {} to {}", style("download").green().bright(), n, f),
+	Action::DeleteLocal(f) => format!("{} {} on computer", style("delete").red().bright(), f),
+	Action::DeleteCalc(n) => format!("{} {} on calculator", style("delete").red().bright(), n),
+	Action::Conflict(n) => format!("{} {} differs on both sides; skipping (use --mode push or pull)",
+				       style("conflict").yellow().bright(), n),
+	Action::KeepLocal(f) => format!("{} {}, which isn't on the calculator (--delete deletes it)",
+					style("keep").yellow().bright(), f),
+	Action::KeepCalc(n) => format!("{} {} on calculator, which isn't in the folder (--delete purges it)",
+				       style("keep").yellow().bright(), n),
+    }
+}
+
+// Like crc_and_output(), but a bad object doesn't stop the sync.
+fn print_info(path: &PathBuf) {
+    match hp_object::crc_file(path) {
+	Ok(info) => println!("  {}", info),
+	Err(e) => eprintln!("  {}: {}: {}", style("warning").yellow().bright(), path.display(), e),
+    }
+}
+
+pub fn sync(dir: &PathBuf, port: Box<dyn serialport::SerialPort>, kermit: &bool,
+	    mode: &SyncMode, delete: &bool, dry_run: &bool, finish: &bool) {
+    if !dir.is_dir() {
+	return crate::helpers::error_handler(format!("Error: {} is not a directory", dir.display()));
+    }
+    let local = match local_objects(dir) {
+	Ok(l) => l,
+	Err(e) => return crate::helpers::error_handler(format!("Error: {}", e)),
+    };
+    let mut server = Server { port, kermit: *kermit };
+    let calc = match server.list_crcs() {
+	Ok(c) => c,
+	Err(e) => return crate::helpers::error_handler(format!("Error: couldn't list calculator directory: {}", e)),
+    };
+    if !calc.directories.is_empty() {
+	println!("{} subdirectories on calculator: {}", style("skipping").yellow().bright(),
+		 calc.directories.join(", "));
+    }
+
+    let actions = plan(&local, &calc.variables, mode, *delete);
+    if actions.is_empty() {
+	println!("{}", style("Already in sync").green().bright());
+    }
+    for action in &actions {
+	println!("{}", describe(action));
+	if *dry_run {
+	    continue;
+	}
+	let result = match action {
+	    Action::Upload(file) => {
+		let path = dir.join(file);
+		server.put(&path).map(|_| print_info(&path))
+	    },
+	    Action::Download(name, file) => {
+		let path = dir.join(file);
+		server.get(name).and_then(|contents| std::fs::write(&path, contents)
+					  .map_err(|e| format!("couldn't write {}: {}", path.display(), e)))
+		    .map(|_| print_info(&path))
+	    },
+	    Action::DeleteLocal(file) => {
+		let path = dir.join(file);
+		std::fs::remove_file(&path).map_err(|e| format!("couldn't delete {}: {}", path.display(), e))
+	    },
+	    Action::DeleteCalc(name) => server.purge(name),
+	    Action::Conflict(_) | Action::KeepLocal(_) | Action::KeepCalc(_) => Ok(()),
+	};
+	if let Err(e) = result {
+	    return crate::helpers::error_handler(format!("Error: {}", e));
+	}
+    }
+    if *dry_run && !actions.is_empty() {
+	println!("Dry run, nothing was changed.");
+    }
+    if *finish {
+	if let Err(e) = server.finish() {
+	    crate::helpers::error_handler(format!("Error: {}", e));
+	}
+    }
+}
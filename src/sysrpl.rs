@@ -0,0 +1,142 @@
+// System RPL decompiler. A program (DOCOL) is a list of objects and
+// pointers to objects ending with SEMI. Objects embedded in it start
+// with their prolog, and anything else is a 5-nibble address, usually
+// of something in ROM. The output is close to what RPLCOMP and Jazz
+// take as input:
+/*
+ * ::
+ *   DUP
+ *   # 1A
+ *   % 1.5
+ *   PTR 12345
+ * ;
+ */
+
+use crate::hp_object;
+use crate::hp_real::{self, HpNumber};
+
+const INDENT: &str = "  ";
+
+// ASCIC name (length, then characters) at `start`. Returns the name
+// and where the next field starts.
+fn read_ascic(nibs: &[u8], start: usize) -> Result<(String, usize), String> {
+    let char_len = hp_object::read_nibbles(nibs, start, 2)? as usize;
+    let name = hp_object::read_hp_string(nibs, start + 2, char_len)?;
+    return Ok((name, start + 2 + char_len * 2));
+}
+
+fn composite(nibs: &[u8], open: &str, close: &str, depth: usize, lines: &mut Vec<String>) -> Result<(), String> {
+    lines.push(format!("{}{}", INDENT.repeat(depth), open));
+    for (start, len) in hp_object::composite_elements(nibs)? {
+	decompile_element(&nibs[start..start + len], depth + 1, lines)?;
+    }
+    lines.push(format!("{}{}", INDENT.repeat(depth), close));
+    return Ok(());
+}
+
+// One element of a composite, which can be a pointer instead of an
+// object.
+fn decompile_element(nibs: &[u8], depth: usize, lines: &mut Vec<String>) -> Result<(), String> {
+    let addr = hp_object::get_prolog(nibs)?;
+    if hp_object::prolog_name(addr).is_some() {
+	return decompile_object(nibs, depth, lines);
+    }
+    let text = match crate::entries::entry_name(addr) {
+	Some(name) => name.to_owned(),
+	None => format!("PTR {:05X}", addr),
+    };
+    lines.push(format!("{}{}", INDENT.repeat(depth), text));
+    return Ok(());
+}
+
+fn decompile_object(nibs: &[u8], depth: usize, lines: &mut Vec<String>) -> Result<(), String> {
+    let prolog = hp_object::get_prolog(nibs)?;
+    let indent = INDENT.repeat(depth);
+    let text = match prolog {
+	0x2911 => format!("# {:X}", hp_object::read_nibbles(nibs, 5, 5)?),
+	0x2933 | 0x2955 | 0x2977 | 0x299d => match hp_real::read_number(nibs)? {
+	    HpNumber::Real(r) if prolog == 0x2955 => format!("%% {}", r),
+	    HpNumber::Real(r) => format!("% {}", r),
+	    HpNumber::Complex(re, im) if prolog == 0x299d => format!("C%% {} {}", re, im),
+	    HpNumber::Complex(re, im) => format!("C% {} {}", re, im),
+	},
+	0x29bf => format!("CHR {}", crate::helpers::hp_char_to_char(hp_object::read_nibbles(nibs, 5, 2)? as u8)),
+	0x2a2c => format!("\"{}\"", crate::data::read_string(nibs)?.replace('"', "\\\"")),
+	0x2a4e => {
+	    // the size counts itself, so it's never less than 5
+	    let size = hp_object::read_nibbles(nibs, 5, 5)? as usize;
+	    if size < 5 {
+		return Err("HXS size is less than its own length".to_owned());
+	    }
+	    let digits: String = match nibs.get(10..5 + size) {
+		Some(d) => d.iter().map(|n| format!("{:X}", n)).collect(),
+		None => return Err("HXS is cut short".to_owned()),
+	    };
+	    format!("HXS {:X} {}", size - 5, digits)
+	},
+	0x2e48 => format!("ID {}", read_ascic(nibs, 5)?.0),
+	0x2e6d => format!("LAM {}", read_ascic(nibs, 5)?.0),
+	0x2e92 => format!("ROMPTR {:X} {:X}", hp_object::read_nibbles(nibs, 5, 3)?, hp_object::read_nibbles(nibs, 8, 3)?),
+	0x2afc => {
+	    // the tagged object follows the tag
+	    let (tag, start) = read_ascic(nibs, 5)?;
+	    lines.push(format!("{}TAG {}", indent, tag));
+	    return decompile_object(&nibs[start..], depth + 1, lines);
+	},
+	0x2d9d => return composite(nibs, "::", ";", depth, lines),
+	0x2a74 => return composite(nibs, "{", "}", depth, lines),
+	0x2ab8 => return composite(nibs, "SYMBOL", ";", depth, lines),
+	0x2ada => return composite(nibs, "UNIT", ";", depth, lines),
+	0x2dcc => {
+	    let len = hp_object::calc_object_size(nibs)? as usize;
+	    if len > nibs.len() {
+		return Err("code object is cut short".to_owned());
+	    }
+	    lines.push(format!("{}CODE", indent));
+	    for line in crate::disasm::disassemble(&nibs[..len], 10, len) {
+		lines.push(format!("{}{}{}", indent, INDENT, line));
+	    }
+	    "ENDCODE".to_owned()
+	},
+	// no SysRPL syntax for the rest, so just say what it is
+	_ => format!("( {} of {} nibbles )", hp_object::prolog_name(prolog).unwrap_or("unknown object"),
+		     hp_object::calc_object_size(nibs)?),
+    };
+    lines.push(format!("{}{}", indent, text));
+    return Ok(());
+}
+
+// Decompile the object at the start of `nibs` into lines of System RPL.
+pub fn decompile(nibs: &[u8]) -> Result<Vec<String>, String> {
+    let mut lines: Vec<String> = Vec::new();
+    decompile_object(nibs, 0, &mut lines)?;
+    return Ok(lines);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hp_object::nibbles;
+
+    #[test]
+    fn program_with_entries_and_objects() {
+	// :: DUP # 1A % 1.5 PTR 12345 ID A "a"b" ;
+	let program = nibbles("D9D20 88130 11920 A1000 33920 000 000000000051 0 54321 \
+			       84E20 10 14 C2A20 B0000 16 22 26 B2130");
+	let lines = decompile(&program).unwrap();
+	assert_eq!(lines, vec!["::", "  DUP", "  # 1A", "  % 1.5", "  PTR 12345", "  ID A", "  \"a\\\"b\"", ";"]);
+    }
+
+    #[test]
+    fn tagged_and_nested_lists() {
+	// :T: { { } }
+	let tagged = nibbles("CFA20 10 45 47A20 47A20 B2130 B2130");
+	assert_eq!(decompile(&tagged).unwrap(), vec!["TAG T", "  {", "    {", "    }", "  }"]);
+    }
+
+    #[test]
+    fn bad_hex_string_size() {
+	assert_eq!(decompile(&nibbles("E4A20 30000")).err(), Some("HXS size is less than its own length".to_owned()));
+	assert_eq!(decompile(&nibbles("E4A20 A0000 12")).err(), Some("HXS is cut short".to_owned()));
+    }
+}
@@ -0,0 +1,225 @@
+// --trace FILE: log every byte that goes over the serial port, with
+// the time since the port was opened and the direction, and a summary
+// of each Kermit and XModem packet. A trace looks like this:
+/*
+ * # alum trace, /dev/ttyUSB0 opened at 1760000000.123 (Unix time)
+ * 0.000112 /dev/ttyUSB0 > 1: 01 2b 20 53 7e 22 20 40 2d 23 59 31 34 0d  |.+ S~" @-#Y14.|
+ * 0.000112 /dev/ttyUSB0 # Kermit S packet, seq 0, 8 data bytes "~\" @-#Y1", check ok
+ * 0.312480 /dev/ttyUSB0 < 2: 01 2b 20 59 7e 22 20 40 2d 23 59 31 3a 0d  |.+ Y~" @-#Y1:.|
+ * 0.312480 /dev/ttyUSB0 # Kermit Y packet, seq 0, 8 data bytes "~\" @-#Y1", check ok
+ */
+// '>' is written by us, '<' is read from the calculator, and '#' is a
+// comment. The number after the direction counts the read() and
+// write() calls, so that --replay can tell which lines came from one
+// call even when two calls happen in the same microsecond. Several
+// ports (xsend --all-ports) share the file, which is why every line
+// has the port name, and why the count is for the whole file.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+static TRACE_FILE: OnceLock<Mutex<File>> = OnceLock::new();
+// read() and write() calls logged so far, on every port
+static CALLS: AtomicU64 = AtomicU64::new(0);
+
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const EOT: u8 = 0x04;
+const CAN: u8 = 0x18;
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+
+// Start tracing every port opened from now on to `path`.
+pub fn start(path: &PathBuf) -> Result<(), String> {
+    let file = match File::create(path) {
+	Ok(f) => f,
+	Err(e) => return Err(format!("couldn't create trace file {}: {}", path.display(), e)),
+    };
+    let _ = TRACE_FILE.set(Mutex::new(file));
+    return Ok(());
+}
+
+// Wrap `port` so that it is traced, if --trace was given.
+pub fn wrap(port: Box<dyn SerialPort>) -> Box<dyn SerialPort> {
+    if TRACE_FILE.get().is_none() {
+	return port;
+    }
+    let name = port.name().unwrap_or("port".to_owned());
+    let opened = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    log(&format!("# alum trace, {} opened at {}.{:03} (Unix time)", name, opened.as_secs(), opened.subsec_millis()));
+    return Box::new(TracePort { inner: port, name, start: Instant::now(), received: Vec::new() });
+}
+
+fn log(line: &str) {
+    if let Some(file) = TRACE_FILE.get() {
+	if let Ok(mut f) = file.lock() {
+	    let _ = writeln!(f, "{}", line);
+	}
+    }
+}
+
+// `bytes` as rows of 16 in hex, then as ASCII, like hexdump -C.
+pub fn hex_rows(bytes: &[u8]) -> Vec<String> {
+    return bytes.chunks(16).map(|row| {
+	let hex: Vec<String> = row.iter().map(|b| format!("{:02x}", b)).collect();
+	let ascii: String = row.iter().map(|b| if (0x20..0x7f).contains(b) { *b as char } else { '.' }).collect();
+	format!("{:<47}  |{}|", hex.join(" "), ascii)
+    }).collect();
+}
+
+// Summarize the packets at the start of `buf`, and return how many
+// bytes that used. With `partial`, a packet that might not be
+// complete yet is left for later.
+pub fn describe_packets(buf: &[u8], partial: bool) -> (usize, Vec<String>) {
+    let mut pos = 0;
+    let mut summaries = Vec::new();
+    while pos < buf.len() {
+	let rest = &buf[pos..];
+	let packet = crate::xmodem::describe(rest).or_else(|| crate::kermit::describe(rest));
+	match packet {
+	    Some((len, summary)) => {
+		summaries.push(summary);
+		pos += len;
+	    },
+	    // a 1K block is the longest thing we'll wait for
+	    None if partial && (rest[0] == SOH || rest[0] == STX) && rest.len() < 1029 => break,
+	    None => {
+		summaries.push(describe_byte(rest[0]));
+		pos += 1;
+	    },
+	}
+    }
+    return (pos, summaries);
+}
+
+// Name of a one-byte message.
+fn describe_byte(b: u8) -> String {
+    match b {
+	ACK => "ACK".to_owned(),
+	NAK => "NAK".to_owned(),
+	EOT => "EOT".to_owned(),
+	CAN => "CAN".to_owned(),
+	0x20..=0x7e => format!("{:?}", b as char),
+	_ => format!("byte {:02X}", b),
+    }
+}
+
+struct TracePort {
+    inner: Box<dyn SerialPort>,
+    name: String,
+    start: Instant,
+    // bytes read that aren't a whole packet yet
+    received: Vec<u8>,
+}
+
+impl TracePort {
+    fn log_bytes(&self, direction: char, bytes: &[u8]) {
+	let time = self.start.elapsed().as_secs_f64();
+	let call = CALLS.fetch_add(1, Ordering::Relaxed) + 1;
+	for row in hex_rows(bytes) {
+	    log(&format!("{:.6} {} {} {}: {}", time, self.name, direction, call, row));
+	}
+    }
+
+    fn comment(&self, text: &str) {
+	log(&format!("{:.6} {} # {}", self.start.elapsed().as_secs_f64(), self.name, text));
+    }
+
+    fn summarize(&self, buf: &[u8], partial: bool) -> usize {
+	let (used, summaries) = describe_packets(buf, partial);
+	for summary in summaries {
+	    self.comment(&summary);
+	}
+	return used;
+    }
+}
+
+impl Read for TracePort {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+	let result = self.inner.read(buf);
+	match &result {
+	    Ok(n) => {
+		self.log_bytes('<', &buf[..*n]);
+		self.received.extend_from_slice(&buf[..*n]);
+		let used = self.summarize(&self.received, true);
+		self.received.drain(..used);
+	    },
+	    Err(e) => self.comment(&format!("read failed: {}", e)),
+	}
+	return result;
+    }
+}
+
+impl Write for TracePort {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+	let result = self.inner.write(buf);
+	match &result {
+	    Ok(n) => {
+		self.log_bytes('>', &buf[..*n]);
+		// we write whole packets, so there's nothing to wait for
+		self.summarize(&buf[..*n], false);
+	    },
+	    Err(e) => self.comment(&format!("write failed: {}", e)),
+	}
+	return result;
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+	return self.inner.flush();
+    }
+}
+
+impl Drop for TracePort {
+    fn drop(&mut self) {
+	// whatever is left is part of a packet that never finished
+	if !self.received.is_empty() {
+	    self.comment(&format!("{} bytes of an incomplete packet", self.received.len()));
+	}
+	self.comment("closed");
+    }
+}
+
+// Everything else goes straight to the real port.
+impl SerialPort for TracePort {
+    fn name(&self) -> Option<String> { self.inner.name() }
+    fn baud_rate(&self) -> serialport::Result<u32> { self.inner.baud_rate() }
+    fn data_bits(&self) -> serialport::Result<DataBits> { self.inner.data_bits() }
+    fn flow_control(&self) -> serialport::Result<FlowControl> { self.inner.flow_control() }
+    fn parity(&self) -> serialport::Result<Parity> { self.inner.parity() }
+    fn stop_bits(&self) -> serialport::Result<StopBits> { self.inner.stop_bits() }
+    fn timeout(&self) -> Duration { self.inner.timeout() }
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+	self.comment(&format!("baud rate set to {}", baud_rate));
+	self.inner.set_baud_rate(baud_rate)
+    }
+    fn set_data_bits(&mut self, data_bits: DataBits) -> serialport::Result<()> { self.inner.set_data_bits(data_bits) }
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> serialport::Result<()> {
+	self.inner.set_flow_control(flow_control)
+    }
+    fn set_parity(&mut self, parity: Parity) -> serialport::Result<()> { self.inner.set_parity(parity) }
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> serialport::Result<()> { self.inner.set_stop_bits(stop_bits) }
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> { self.inner.set_timeout(timeout) }
+    fn write_request_to_send(&mut self, level: bool) -> serialport::Result<()> { self.inner.write_request_to_send(level) }
+    fn write_data_terminal_ready(&mut self, level: bool) -> serialport::Result<()> {
+	self.inner.write_data_terminal_ready(level)
+    }
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> { self.inner.read_clear_to_send() }
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> { self.inner.read_data_set_ready() }
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> { self.inner.read_ring_indicator() }
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> { self.inner.read_carrier_detect() }
+    fn bytes_to_read(&self) -> serialport::Result<u32> { self.inner.bytes_to_read() }
+    fn bytes_to_write(&self) -> serialport::Result<u32> { self.inner.bytes_to_write() }
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+	self.comment(&format!("cleared {:?} buffer", buffer_to_clear));
+	self.inner.clear(buffer_to_clear)
+    }
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> { self.inner.try_clone() }
+    fn set_break(&self) -> serialport::Result<()> { self.inner.set_break() }
+    fn clear_break(&self) -> serialport::Result<()> { self.inner.clear_break() }
+}
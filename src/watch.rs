@@ -0,0 +1,216 @@
+// `xsend --watch` and `ksend --watch`: keep the port open and send
+// objects again whenever they change on disk, for an edit, compile,
+// test loop. The server has to be running on the calculator, because
+// XRECV and RECV quit after one file.
+
+// We poll instead of asking the OS for change notifications. It works
+// the same everywhere, and a file is only sent once it stops changing
+// between two polls, so we don't send half of a file that is still
+// being written.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+use console::style;
+
+use crate::hp_object;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// In watched directories, files with these extensions are compiled
+// with --compile. Anything else that isn't an HP object is ignored.
+const SOURCE_EXTENSIONS: [&str; 2] = ["s", "rpl"];
+
+struct Watcher {
+    port: Box<dyn serialport::SerialPort>,
+    kermit: bool,
+    compile: Option<String>,
+    // contents at the last poll, to tell when a file stops changing
+    seen: HashMap<PathBuf, Vec<u8>>,
+    // contents when last sent (or when we started watching)
+    sent: HashMap<PathBuf, Vec<u8>>,
+}
+
+fn is_hidden(path: &PathBuf) -> bool {
+    match path.file_name().and_then(|n| n.to_str()) {
+	// dotfiles and editor backups
+	Some(name) => name.starts_with('.') || name.ends_with('~') || name.starts_with('#'),
+	None => true,
+    }
+}
+
+fn is_source(path: &PathBuf) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+	Some(ext) => SOURCE_EXTENSIONS.contains(&ext),
+	None => false,
+    }
+}
+
+// The files to watch under `paths`: named files always, and objects
+// and source files in directories.
+fn watched_files(paths: &[PathBuf], compile: bool) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for path in paths {
+	if !path.is_dir() {
+	    files.push(path.to_path_buf());
+	    continue;
+	}
+	let mut children: Vec<PathBuf> = match std::fs::read_dir(path) {
+	    Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+	    Err(_) => continue,
+	};
+	children.sort();
+	for child in children {
+	    if is_hidden(&child) {
+		continue;
+	    }
+	    if child.is_dir() {
+		files.extend(watched_files(&[child], compile));
+	    } else if (compile && is_source(&child)) || is_object(&std::fs::read(&child).unwrap_or_default()) {
+		files.push(child);
+	    }
+	}
+    }
+    return files;
+}
+
+fn is_object(contents: &[u8]) -> bool {
+    return contents.starts_with(b"HPHP48");
+}
+
+// `s` quoted for the shell that runs --compile, so that paths with
+// spaces or characters like ; and $ stay one argument.
+fn shell_quote(s: &str) -> String {
+    match cfg!(windows) {
+	// Windows file names can't have a " in them
+	true => format!("\"{}\"", s),
+	false => format!("'{}'", s.replace('\'', "'\\''")),
+    }
+}
+
+// Where --compile writes the object for `source`: the same name
+// without the extension, so GAME.s becomes GAME.
+fn compiled_path(source: &PathBuf) -> PathBuf {
+    return source.with_extension("");
+}
+
+impl Watcher {
+    fn send(&mut self, path: &PathBuf) {
+	println!("Sending {:?}...", style(path.file_name().unwrap()).yellow().bright());
+	let sent = match self.kermit {
+	    true => crate::kermit::send_file(path, &mut self.port, &false),
+	    false => crate::xmodem::send_file_conn4x(path, &mut self.port, &false),
+	};
+	// the server may still be there, or the calculator may be back by
+	// the next change, so keep watching
+	if let Err(e) = sent {
+	    return eprintln!("{}: couldn't send {}: {} (it will be sent when it changes again)",
+			     style("error").red().bright(), path.display(), e);
+	}
+	print!("File info:\n  ");
+	hp_object::crc_and_output(path);
+    }
+
+    // Run the --compile command on `source`, with {in} and {out}
+    // replaced by the quoted source and object paths. They are also in
+    // the environment as IN and OUT. Returns the object.
+    fn compile(&self, command: &str, source: &PathBuf) -> Result<PathBuf, String> {
+	let out = compiled_path(source);
+	let command = command
+	    .replace("{in}", &shell_quote(&source.display().to_string()))
+	    .replace("{out}", &shell_quote(&out.display().to_string()));
+	println!("Compiling {:?}: {}", style(source.file_name().unwrap()).yellow().bright(), command);
+	let mut shell = match cfg!(windows) {
+	    true => Command::new("cmd"),
+	    false => Command::new("sh"),
+	};
+	let status = shell.arg(if cfg!(windows) { "/C" } else { "-c" }).arg(&command)
+	    .env("IN", source).env("OUT", &out).status();
+	match status {
+	    Ok(s) if s.success() => {},
+	    Ok(s) => return Err(format!("compile command failed ({})", s)),
+	    Err(e) => return Err(format!("couldn't run compile command: {}", e)),
+	}
+	match std::fs::read(&out) {
+	    Ok(contents) if is_object(&contents) => Ok(out),
+	    Ok(_) => Err(format!("{} is not an HP object after compiling", out.display())),
+	    Err(e) => Err(format!("couldn't read {}: {}", out.display(), e)),
+	}
+    }
+
+    // Handle one file that changed and then stayed the same for a poll.
+    fn changed(&mut self, path: &PathBuf, contents: Vec<u8>) {
+	self.sent.insert(path.to_path_buf(), contents.clone());
+	if is_object(&contents) {
+	    self.send(path);
+	    return;
+	}
+	let command = match &self.compile {
+	    Some(c) => c.clone(),
+	    None => {
+		eprintln!("{}: {} is not an HP object and there is no --compile command; not sending",
+			  style("warning").yellow().bright(), path.display());
+		return;
+	    },
+	};
+	match self.compile(&command, path) {
+	    Ok(object) => {
+		// if the object is in a watched directory, this keeps
+		// the next poll from sending it again
+		let object_contents = std::fs::read(&object).unwrap_or_default();
+		self.seen.insert(object.clone(), object_contents.clone());
+		self.sent.insert(object.clone(), object_contents);
+		self.send(&object);
+	    },
+	    Err(e) => eprintln!("{}: {}", style("error").red().bright(), e),
+	}
+    }
+
+    fn poll(&mut self, paths: &[PathBuf]) {
+	for path in watched_files(paths, self.compile.is_some()) {
+	    // the file may be in the middle of being replaced
+	    let contents = match std::fs::read(&path) {
+		Ok(c) => c,
+		Err(_) => continue,
+	    };
+	    let stable = self.seen.get(&path) == Some(&contents);
+	    self.seen.insert(path.clone(), contents.clone());
+	    if stable && self.sent.get(&path) != Some(&contents) {
+		self.changed(&path, contents);
+	    }
+	}
+    }
+}
+
+// Watch `paths` and send what changes on `port`, until interrupted.
+pub fn watch(paths: &[PathBuf], port: Box<dyn serialport::SerialPort>, kermit: &bool, compile: &Option<String>) {
+    for path in paths {
+	if !path.exists() {
+	    return crate::helpers::error_handler(format!("Error: {} does not exist", path.display()));
+	}
+    }
+    let mut watcher = Watcher {
+	port,
+	kermit: *kermit,
+	compile: compile.clone(),
+	seen: HashMap::new(),
+	sent: HashMap::new(),
+    };
+    // only changes are sent, so the files as they are now are the
+    // starting point
+    for path in watched_files(paths, compile.is_some()) {
+	if let Ok(contents) = std::fs::read(&path) {
+	    watcher.seen.insert(path.clone(), contents.clone());
+	    watcher.sent.insert(path, contents);
+	}
+    }
+    println!("Watching {} file{} on {}. Press Ctrl-C to stop.",
+	     watcher.seen.len(), if watcher.seen.len() == 1 { "" } else { "s" },
+	     style(watcher.port.name().unwrap_or_default()).green().bright());
+    loop {
+	std::thread::sleep(POLL_INTERVAL);
+	watcher.poll(paths);
+    }
+}
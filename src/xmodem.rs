@@ -8,7 +8,7 @@
 use std::path::PathBuf;
 use std::fs::File;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::io::Write;
 
 use serialport;
@@ -29,6 +29,10 @@ const CAN: u8 = 0x18;
 // XModem is supposed to use 0x1a, but the HP uses 0x00.
 const SUB: u8 = 0x00;//0x1a; // used as packet filler, ascii code SUB (substitute)
 
+// How many times to try one block, sending or receiving, before giving
+// up, like most XModem programs.
+const MAX_RETRIES: u32 = 10;
+
 // packet_count_offset is an adjustment to the packet-counting loop
 // inside this function. For example, if the offset is 3, then the
 // first packet generated by this loop will have packet number 4
@@ -150,7 +154,6 @@ fn data_to_conn4x_packets(data: &Vec<u8>) -> Vec<Vec<u8>> {
     // Number of 1K-byte packets to use
     let mut packet_offset = 0usize;
     let packet_count = data.len() / 1024;
-    //println!("data.len() is {:?}, packet_count in conn4x_packets is {:?}", data.len(), packet_count);
     for i in 0..packet_count {
 	packet_offset += 1;
 	
@@ -190,100 +193,160 @@ fn data_to_conn4x_packets(data: &Vec<u8>) -> Vec<Vec<u8>> {
     return packet_list;
 }
 
-// Looks for ack_char on `port`, returns true if char found or false if NAK found.
-fn wait_for_char(port: &mut Box<dyn serialport::SerialPort>, ack_char: u8) -> u8 {
-    let mut buf: [u8; 1] = [0; 1];
+// Wait for `ack_char` on `port`, and return it, or NAK if that comes
+// first. Two CANs in a row mean the calculator cancelled, like in
+// read_reply(). Anything else is noise from the line and skipped.
+fn wait_for_char(port: &mut Box<dyn serialport::SerialPort>, ack_char: u8) -> Result<u8, String> {
+    let deadline = crate::serial::deadline(port);
+    let mut buf: Vec<u8> = Vec::new();
     loop {
-	match port.read(buf.as_mut_slice()) {
-	    Ok(_) => {
-		let byte = *buf.get(0).unwrap();
-		if byte == ack_char {
-		    break;
-		} else {
-		    return byte;
+	if let Err(e) = crate::serial::fill(port, &mut buf, 1, deadline) {
+	    return Err(format!("failed to read from serial port: {}", e));
+	}
+	match buf[0] {
+	    byte if byte == ack_char || byte == NAK => return Ok(byte),
+	    CAN => {
+		if let Err(e) = crate::serial::fill(port, &mut buf, 2, deadline) {
+		    return Err(format!("failed to read from serial port: {}", e));
+		}
+		if buf[1] == CAN {
+		    return Err("transfer cancelled by calculator.".to_string());
 		}
 	    },
-	    Err(e) => crate::helpers::error_handler(format!("Error: failed to read from serial port: {:?}", e)),
+	    _ => {},
 	}
-
-	//println!("waiting for ACK");
+	buf.remove(0);
     }
-    return ack_char;
 }
 
+// Give up on a transfer. Two CANs in a row tell the other side to
+// stop as well.
+fn cancel(port: &mut Box<dyn serialport::SerialPort>) {
+    let _ = port.write_all(&[CAN, CAN]);
+}
 
+// What the calculator answered to a block or EOT.
+enum Reply {
+    Ack,
+    Nak,
+    Cancel,
+    // no answer in time, and why
+    Timeout(String),
+}
 
-// The way packets are sent and responses are handled don't change.
-
-fn send_packets(packet_list: &Vec<Vec<u8>>, port: &mut Box<dyn serialport::SerialPort>) {
-    let pb = crate::helpers::get_progress_bar(packet_list.len() as u64);
-    
-    for (pos, packet) in packet_list.iter().enumerate() {
-	let mut retry_count = 0;
-	match port.write(packet) {
-	    Ok(_) => {},
-	    Err(e) => crate::helpers::error_handler(format!("Error: failed to write packet {:?}", e)),
+// Wait for the answer to what we just sent. Noise before it is
+// skipped, and it takes two CANs in a row to cancel, because a single
+// CAN could be noise too.
+fn read_reply(port: &mut Box<dyn serialport::SerialPort>) -> Reply {
+    let deadline = crate::serial::deadline(port);
+    let mut buf: Vec<u8> = Vec::new();
+    loop {
+	if let Err(e) = crate::serial::fill(port, &mut buf, 1, deadline) {
+	    return Reply::Timeout(e);
 	}
-	
-	// wait for ACK on current packet
-	let c = wait_for_char(port, ACK);
-	//println!("c is {:?}", c);
-	if c == NAK {
-	    match port.write(packet) {
-		Ok(_) => {},
-		Err(e) => crate::helpers::error_handler(format!("Error: failed to read char for packet {:?}: {:?}", pos, e)),
-	    }
-	    retry_count += 1;
-	    if retry_count == 3 {
-		// Something deeper is wrong, give up
-		crate::helpers::error_handler(format!("Error: failed on packet {:?} after 3 tries, giving up.", pos));
-	    }
-	} else if c == CAN {
-	    // cancel, just exit.
-	    pb.abandon();
-	    crate::helpers::error_handler("Error: transfer cancelled by calculator.".to_string());
-	} else if c == ACK {
-	    // on ACK, increment and check if we need to send EOT
-	    pb.inc(1);
-	    // if we successfully sent the last packet, send EOT after the
-	    // last ACK. This will trigger another ACK, which we look for
-	    // below (but probably don't need to)
-	    if pos == packet_list.len() - 1 {
-		//println!("sending EOT");
-		let wr_buf: [u8; 1] = [EOT];
-		match port.write(&wr_buf) {
-		    Ok(_) => {},
-		    Err(e) => crate::helpers::error_handler(format!("Error: failed to send EOT: {:?}", e)),
+	match buf[0] {
+	    ACK => return Reply::Ack,
+	    NAK => return Reply::Nak,
+	    CAN => {
+		if let Err(e) = crate::serial::fill(port, &mut buf, 2, deadline) {
+		    return Reply::Timeout(e);
 		}
-		wait_for_char(port, ACK);
-	    }
-	} else {
+		if buf[1] == CAN {
+		    return Reply::Cancel;
+		}
+	    },
+	    _ => {},
+	}
+	buf.remove(0);
+    }
+}
+
+// Where send_packets() is in a transfer.
+enum Sending {
+    // block number n (from 0) of the list
+    Block(usize),
+    End,
+    Done,
+}
+
+// Send each block until it is ACKed, then EOT until that is ACKed too.
+// A NAK or no answer sends the same thing again, up to MAX_RETRIES
+// times; after that, or if the calculator cancels, the transfer fails.
+fn send_packets(packet_list: &Vec<Vec<u8>>, port: &mut Box<dyn serialport::SerialPort>) -> Result<(), String> {
+    let pb = crate::helpers::get_progress_bar(packet_list.len() as u64);
+    let mut state = match packet_list.is_empty() {
+	true => Sending::End,
+	false => Sending::Block(0),
+    };
+    let mut retries = 0;
+
+    loop {
+	let (bytes, what): (&[u8], String) = match state {
+	    Sending::Block(n) => (&packet_list[n], format!("packet {}", n + 1)),
+	    Sending::End => (&[EOT], "EOT".to_string()),
+	    Sending::Done => break,
+	};
+	// packet_delay from the config file, for slow adapters
+	thread::sleep(crate::config::packet_delay());
+	if let Err(e) = port.write_all(bytes) {
 	    pb.abandon();
-	    crate::helpers::error_handler("Error: unexpected character during transfer.".to_string());
+	    return Err(format!("failed to write {}: {:?}", what, e));
+	}
+
+	match read_reply(port) {
+	    Reply::Ack => {
+		retries = 0;
+		state = match state {
+		    Sending::Block(n) => {
+			pb.inc(1);
+			match n + 1 < packet_list.len() {
+			    true => Sending::Block(n + 1),
+			    false => Sending::End,
+			}
+		    },
+		    _ => Sending::Done,
+		};
+	    },
+	    Reply::Cancel => {
+		pb.abandon();
+		return Err("transfer cancelled by calculator.".to_string());
+	    },
+	    // send it again
+	    Reply::Nak | Reply::Timeout(_) if retries < MAX_RETRIES => retries += 1,
+	    Reply::Nak => {
+		pb.abandon();
+		cancel(port);
+		return Err(format!("failed on {} after {} tries, giving up.", what, MAX_RETRIES + 1));
+	    },
+	    Reply::Timeout(e) => {
+		pb.abandon();
+		cancel(port);
+		return Err(format!("no answer for {} after {} tries ({}), giving up.", what, MAX_RETRIES + 1, e));
+	    },
 	}
-	
-	
     }
     // make the progress bar visible on screen
     pb.finish();
+    return Ok(());
 }
 
 
 
-fn finish_server(port: &mut Box<dyn serialport::SerialPort>) {
+pub fn finish_server(port: &mut Box<dyn serialport::SerialPort>) -> Result<(), String> {
     // needed to make Q actually work
     thread::sleep(Duration::from_millis(300));
     // send Q to server, which tells server to exit
     let buf: [u8; 1] = ['Q' as u8];
-    match port.write(&buf) {
-	Ok(_) => {},
-	Err(e) => crate::helpers::error_handler(format!("error writing packet: {:?}", e)),
+    match port.write_all(&buf) {
+	Ok(_) => return Ok(()),
+	Err(e) => return Err(format!("error writing packet: {:?}", e)),
     };
-
 }
+
 // Send `path` to the calculator with Conn4x-style XModem.
-pub fn send_file_conn4x(path: &PathBuf, port: &mut Box<dyn serialport::SerialPort>, finish: &bool) {
-    let file_contents = crate::helpers::get_file_contents(path);
+pub fn send_file_conn4x(path: &PathBuf, port: &mut Box<dyn serialport::SerialPort>, finish: &bool)
+			-> Result<(), String> {
+    let file_contents = crate::helpers::get_file_contents(path)?;
     
     let packet_list = data_to_conn4x_packets(&file_contents);
 
@@ -293,35 +356,35 @@ pub fn send_file_conn4x(path: &PathBuf, port: &mut Box<dyn serialport::SerialPor
 	hp_fname.push(crate::helpers::char_to_hp_char(i));
     }
     
-    match port.write(&create_command_packet(hp_fname, 'P')) {
-	Ok(_) => {},
-	Err(e) => crate::helpers::error_handler(format!("error writing packet: {:?}", e)),
-    };
+    if let Err(e) = port.write_all(&create_command_packet(hp_fname, 'P')) {
+	return Err(format!("error writing packet: {:?}", e));
+    }
     
-    wait_for_char(port, ACK);
+    if wait_for_char(port, ACK)? != ACK {
+	return Err("got NAK from server when sending 'put' command.".to_string());
+    }
     
     // XModem Server sends D to indicate that it's ready for a
     // Conn4x-style XModem transfer
-    wait_for_char(port, 'D' as u8);
+    if wait_for_char(port, 'D' as u8)? != 'D' as u8 {
+	return Err("got NAK from server instead of D after 'put' command.".to_string());
+    }
     
     // Now send packet_list to the serialport
-    send_packets(&packet_list, port);
+    send_packets(&packet_list, port)?;
     if *finish {
-	finish_server(port);
+	finish_server(port)?;
     }
-
-    
+    return Ok(());
 }
 
-pub fn send_file_normal(path: &PathBuf, port: &mut Box<dyn serialport::SerialPort>) {
-    let file_contents = crate::helpers::get_file_contents(path);
+pub fn send_file_normal(path: &PathBuf, port: &mut Box<dyn serialport::SerialPort>) -> Result<(), String> {
+    let file_contents = crate::helpers::get_file_contents(path)?;
     
-    wait_for_char(port, NAK);
+    wait_for_char(port, NAK)?;
     
     let packet_list = data_to_128_packets(&file_contents, 0, ChecksumMode::Normal);
-    //println!("{:?}", &packet_list[0..256]);
-    send_packets(&packet_list, port);
-
+    return send_packets(&packet_list, port);
 }
 
 
@@ -366,18 +429,15 @@ fn create_command_packet(data: Vec<u8>, cmd: char) -> Vec<u8> {
 // bytes if needed. Without this byte, a positive real number will
 // become correct.
 pub fn get_file(path: &PathBuf, port: &mut Box<dyn serialport::SerialPort>, direct: &bool,
-		overwrite: &bool, finish: &bool) -> PathBuf {
+		overwrite: &bool, finish: &bool) -> Result<PathBuf, String> {
     let final_path = match overwrite {
 	true => path.to_path_buf(),
 	false => crate::helpers::get_unique_path(path.to_path_buf()),
     };
-    println!("final_path in get_file is {:?}", final_path);
     // original_fname is the filename only of the path passed to the code
     // final_fname is the filename only of the path we're writing to
     let original_fname = path.file_name().unwrap().to_str().unwrap();
     let final_fname = final_path.file_name().unwrap().to_str().unwrap();
-    
-    let mut file = File::create(&final_path).unwrap();
 
     // hp_fname is the list of bytes we actually send to the calculator, with HP 48 byte conversion
     let mut hp_fname: Vec<u8> = Vec::new();
@@ -393,90 +453,233 @@ pub fn get_file(path: &PathBuf, port: &mut Box<dyn serialport::SerialPort>, dire
 		style(final_fname).yellow().bright(),
 		style(port.name().unwrap()).green().bright()));
     
-    // We push to a Vec<u8> then write to the file.
-    let mut file_contents: Vec<u8> = Vec::new();
+    let received = match direct {
+	true => receive_file(port),
+	false => request_file(hp_fname, port).and_then(|_| receive_file(port)),
+    };
+    let (file_contents, stats) = match received {
+	Ok(r) => r,
+	Err(e) => {
+	    pb.abandon();
+	    return Err(e);
+	},
+    };
+    
+    // only create the file once there's something to put in it
+    if let Err(e) = File::create(&final_path).and_then(|mut file| file.write_all(&file_contents)) {
+	return Err(format!("failed to write to output file: {:?}", e));
+    }
+
+    if *finish {
+	finish_server(port)?;
+    }
 
-    if !direct {
-	// Tell XModem server to send file
-	match port.write(&create_command_packet(hp_fname, 'G')) {
-	    Ok(_) => {},
-	    Err(e) => crate::helpers::error_handler(
-		format!("Error: failed to write packet writing packet {:?}", e)),
+    pb.finish_with_message(
+	format!("Receiving {:?} from {}...{} Got {}.",
+		style(path.file_name().unwrap()).yellow().bright(),
+		style(port.name().unwrap()).green().bright(),
+		style("done!").green().bright(),
+		stats.summary())
+    );
+
+    return Ok(final_path); // used in main for crc calculation
+}
+
+// Tell the XModem server to send the variable `hp_fname` (already in
+// HP 48 characters).
+fn request_file(hp_fname: Vec<u8>, port: &mut Box<dyn serialport::SerialPort>) -> Result<(), String> {
+    if let Err(e) = port.write_all(&create_command_packet(hp_fname, 'G')) {
+	return Err(format!("failed to write packet writing packet {:?}", e));
+    }
+    
+    // Wait for ACK from server about command
+    if wait_for_char(port, ACK)? != ACK {
+	return Err("got NAK from server when sending 'get' command.".to_string());
+    }
+    return Ok(());
+}
+
+// What the calculator sends when we ask for a block.
+enum Block {
+    // the block number and the data, or None if the checksum was wrong
+    Data(u8, Option<Vec<u8>>),
+    End,
+    Cancel,
+}
+
+// How a receive went, for the message at the end.
+#[derive(Default)]
+struct ReceiveStats {
+    blocks: u32,
+    bad_checksums: u32,
+    timeouts: u32,
+    duplicates: u32,
+}
+
+impl ReceiveStats {
+    // "13 packets", then any trouble, like "13 packets, 1 timeout"
+    fn summary(&self) -> String {
+	let count = |n: u32, word: &str| format!("{} {}{}", n, word, if n == 1 { "" } else { "s" });
+	let mut parts = vec![count(self.blocks, "packet")];
+	for (n, word) in [(self.bad_checksums, "bad checksum"), (self.timeouts, "timeout"), (self.duplicates, "duplicate")] {
+	    if n > 0 {
+		parts.push(count(n, word));
+	    }
 	}
-	
-	// Wait for ACK from server about command
-	if wait_for_char(port, ACK) != ACK {
-	    crate::helpers::error_handler("Error: got NAK from server when sending 'get' command.".to_string());
+	return parts.join(", ");
+    }
+}
+
+
+// Read the next block from the calculator, however many pieces it
+// comes in. Noise before the block is skipped, and so is an SOH whose
+// block number and complement don't match, because it can't be the
+// start of a block. Like read_reply(), it takes two CANs in a row to
+// cancel.
+fn read_block(port: &mut Box<dyn serialport::SerialPort>, deadline: Instant) -> Result<Block, String> {
+    let mut buf: Vec<u8> = Vec::new();
+    loop {
+	crate::serial::fill(port, &mut buf, 1, deadline)?;
+	let size = match buf[0] {
+	    SOH => 128,
+	    STX => 1024,
+	    EOT => return Ok(Block::End),
+	    CAN => {
+		crate::serial::fill(port, &mut buf, 2, deadline)?;
+		if buf[1] == CAN {
+		    return Ok(Block::Cancel);
+		}
+		buf.remove(0);
+		continue;
+	    },
+	    _ => {
+		buf.remove(0);
+		continue;
+	    },
+	};
+	crate::serial::fill(port, &mut buf, 3, deadline)?;
+	if buf[1] as u32 + buf[2] as u32 != 255 {
+	    buf.remove(0);
+	    continue;
 	}
-	//println!("got ACK");
+	// header, data, and the one-byte checksum
+	crate::serial::fill(port, &mut buf, 3 + size + 1, deadline)?;
+	let data = buf[3..3 + size].to_vec();
+	let checksum: u32 = data.iter().map(|b| *b as u32).sum();
+	return Ok(Block::Data(buf[1], match checksum as u8 == buf[3 + size] {
+	    true => Some(data),
+	    false => None,
+	}));
     }
+}
+
+// Receive one file from a server or direct XSEND, and return its
+// contents without the padding and how it went. A block with a bad
+// checksum, or one that doesn't come in time, is asked for again with
+// a NAK, up to MAX_RETRIES times. A block we already have is one
+// whose ACK got lost, so it's ACKed again and dropped.
+fn receive_file(port: &mut Box<dyn serialport::SerialPort>) -> Result<(Vec<u8>, ReceiveStats), String> {
+    // We push to a Vec<u8> then write to the file.
+    let mut file_contents: Vec<u8> = Vec::new();
 
-    // This is needed, probably because the calculator is pretty slow.
-    thread::sleep(Duration::from_millis(500));
     // Initiate first packet from calculator by sending NAK
     let mut byte_buf: [u8; 1] = [NAK];
 
-    match port.write(&byte_buf) {
-	Ok(_) => {},
-	Err(e) => crate::helpers::error_handler(format!("Error: failed to write initial NAK: {:?}", e)),
+    if let Err(e) = port.write_all(&byte_buf) {
+	return Err(format!("failed to write initial NAK: {:?}", e));
     }
     
-    let mut packet_buf = vec![0; 132];
-    let mut packet_counter = 0u32;
+    let mut stats = ReceiveStats::default();
+    // failed tries at the block we're waiting for, so after
+    // MAX_RETRIES NAKs, the next failure gives up
+    let mut failures = 0;
+    // the calculator may not be listening for the first NAK yet, so
+    // until it starts, NAK again every second
+    let start = Instant::now();
+    let mut started = false;
     
     loop {
-	// Also needed, as far as I can tell.
-	thread::sleep(Duration::from_millis(300));
-	match port.read(packet_buf.as_mut_slice()) {
-	    Ok(_) => {},
-	    Err(e) => crate::helpers::error_handler(format!("error reading packet: {:?}", e)),
+	let deadline = match started {
+	    true => crate::serial::deadline(port),
+	    false => (Instant::now() + Duration::from_secs(1)).min(start + port.timeout()),
 	};
-
-	if packet_buf[0] == EOT {
-	    byte_buf = [ACK];
-	    match port.write(&byte_buf) {
-		Ok(_) => {},
-		Err(e) => crate::helpers::error_handler(format!("Error: failed to write ACK for EOT: {:?}", e)),
-	    }
-	    // transmission finished
-	    break;
-	} else if packet_buf[0] == CAN {
-	    pb.println(format!("Received cancel from remote side, exiting."));
-	    crate::helpers::error_handler("".to_string());
-	}
-	
-	// verify checksum of this packet
-	let mut checksum = 0u32;
-	for i in &packet_buf[3..131] {
-	    checksum += *i as u32;
-	}
-
-	//println!("calculated checksum is {:#x}, packet checksum is {:#x}", checksum as u8, packet_buf[131]);
-	if checksum as u8 == packet_buf[131] {
-	    byte_buf = [ACK];
-	    // put this here instead of in the initial packet_buf
-	    // read, because we only actually get a packet when the
-	    // checksum matches and it's not an EOT.
-	    //println!("read packet {:?}", packet_counter);
-	    match port.write(&byte_buf) {
-		Ok(_) => {},
-		Err(e) => crate::helpers::error_handler(format!(
-		    "Error: failed to write ACK for packet {:?}: {:?}", packet_counter, e)),
-	    }
-	    file_contents.extend_from_slice(&packet_buf[3..131]);
-	} else {
-	    // currently untested...
-	    eprintln!("Checksum failed for packet {:?}, sending NAK and trying again.", packet_counter);
-	    byte_buf = [NAK];
-	    match port.write(&byte_buf) {
-		Ok(_) => {},
-		Err(e) => crate::helpers::error_handler(format!(
-		    "Error: failed to write NAK for packet {:?}: {:?}", packet_counter, e)),
-	    }
-	    continue; // skip packet counter increment
+	let block = match read_block(port, deadline) {
+	    Ok(b) => b,
+	    Err(_) if !started && start.elapsed() < port.timeout() => {
+		if let Err(e) = port.write_all(&[NAK]) {
+		    return Err(format!("failed to write initial NAK: {:?}", e));
+		}
+		continue;
+	    },
+	    Err(e) if !started => {
+		cancel(port);
+		return Err(format!("the calculator didn't start sending: {}", e));
+	    },
+	    // nothing, or only part of a block
+	    Err(e) => {
+		stats.timeouts += 1;
+		failures += 1;
+		if failures > MAX_RETRIES {
+		    cancel(port);
+		    return Err(format!("no packet {} after {} tries ({}), giving up.", stats.blocks + 1, failures, e));
+		}
+		eprintln!("Timed out waiting for packet {:?}, sending NAK and trying again.", stats.blocks + 1);
+		if let Err(e) = port.write_all(&[NAK]) {
+		    return Err(format!("failed to write NAK for packet {:?}: {:?}", stats.blocks + 1, e));
+		}
+		continue;
+	    },
+	};
+	started = true;
+
+	// block numbers start at 1 and wrap around after 255
+	let expected = (stats.blocks + 1) as u8;
+	match block {
+	    Block::End => {
+		byte_buf = [ACK];
+		if let Err(e) = port.write_all(&byte_buf) {
+		    return Err(format!("failed to write ACK for EOT: {:?}", e));
+		}
+		// transmission finished
+		break;
+	    },
+	    Block::Cancel => return Err("transfer cancelled by calculator.".to_string()),
+	    Block::Data(seq, Some(data)) if seq == expected => {
+		byte_buf = [ACK];
+		if let Err(e) = port.write_all(&byte_buf) {
+		    return Err(format!("failed to write ACK for packet {:?}: {:?}", stats.blocks + 1, e));
+		}
+		file_contents.extend_from_slice(&data);
+		stats.blocks += 1;
+		failures = 0;
+	    },
+	    // our ACK for the last block got lost, so the calculator sent
+	    // it again
+	    Block::Data(seq, Some(_)) if stats.blocks > 0 && seq == expected.wrapping_sub(1) => {
+		stats.duplicates += 1;
+		byte_buf = [ACK];
+		if let Err(e) = port.write_all(&byte_buf) {
+		    return Err(format!("failed to write ACK for packet {:?}: {:?}", stats.blocks, e));
+		}
+	    },
+	    Block::Data(seq, Some(_)) => {
+		cancel(port);
+		return Err(format!("got packet {} when expecting packet {}, giving up.", seq, expected));
+	    },
+	    Block::Data(_, None) => {
+		stats.bad_checksums += 1;
+		failures += 1;
+		if failures > MAX_RETRIES {
+		    cancel(port);
+		    return Err(format!("bad checksum on packet {} after {} tries, giving up.", stats.blocks + 1, failures));
+		}
+		eprintln!("Checksum failed for packet {:?}, sending NAK and trying again.", stats.blocks + 1);
+		byte_buf = [NAK];
+		if let Err(e) = port.write_all(&byte_buf) {
+		    return Err(format!("failed to write NAK for packet {:?}: {:?}", stats.blocks + 1, e));
+		}
+	    },
 	}
-
-	packet_counter += 1;
     }
 
     // we need to iterate backwards over file_contents and remove
@@ -503,28 +706,81 @@ pub fn get_file(path: &PathBuf, port: &mut Box<dyn serialport::SerialPort>, dire
     for _ in final_zero..file_contents.len() {
 	file_contents.remove(final_zero);
     }
-    
-    match file.write_all(&file_contents) {
-	Ok(_) => {},
-	Err(e) => crate::helpers::error_handler(format!("Error: failed to write to output file: {:?}", e)),
+    return Ok((file_contents, stats));
+}
+
+// Get the variable `name` from the XModem server into memory.
+pub fn get_data(name: &str, port: &mut Box<dyn serialport::SerialPort>) -> Result<Vec<u8>, String> {
+    let hp_name: Vec<u8> = name.chars().map(crate::helpers::char_to_hp_char).collect();
+    request_file(hp_name, port)?;
+    let pb = crate::helpers::get_spinner(format!("Receiving {}...", style(name).yellow().bright()));
+    let received = receive_file(port);
+    pb.finish_and_clear();
+    let (contents, _) = received?;
+    return Ok(contents);
+}
+
+// Have the XModem server run `command` as if it was typed on the
+// command line.
+pub fn execute(command: &str, port: &mut Box<dyn serialport::SerialPort>) -> Result<(), String> {
+    let data: Vec<u8> = command.chars().map(crate::helpers::char_to_hp_char).collect();
+    if let Err(e) = port.write_all(&create_command_packet(data, 'E')) {
+	return Err(format!("failed to write command packet: {:?}", e));
+    }
+    if wait_for_char(port, ACK)? != ACK {
+	return Err("got NAK from server when sending 'execute' command.".to_string());
     }
+    return Ok(());
+}
 
-    if *finish {
-	finish_server(port);
+// See if an XModem server is listening on `port`, for `alum detect`,
+// by sending an empty execute command. Unlike the functions above,
+// this doesn't fail if nothing answers.
+pub fn ping(port: &mut Box<dyn serialport::SerialPort>) -> bool {
+    if port.write_all(&create_command_packet(Vec::new(), 'E')).is_err() {
+	return false;
     }
+    let mut buf: [u8; 1] = [0; 1];
+    // NAK means it didn't like the packet, but it's still a server
+    return match port.read(&mut buf) {
+	Ok(1) => buf[0] == ACK || buf[0] == NAK,
+	_ => false,
+    };
+}
 
-    pb.finish_with_message(
-	format!("Receiving {:?} from {}...{} Got {:?} {}.",
-		style(path.file_name().unwrap()).yellow().bright(),
-		style(port.name().unwrap()).green().bright(),
-		style("done!").green().bright(),
-		packet_counter,
-		match packet_counter {
-		    1 => "packet",
-		    _ => "packets",
+// For --trace: if `buf` starts with a whole XModem block or server
+// command, return its length and a summary of it. Blocks from us
+// have Conn4x CRCs, and blocks from the calculator have plain
+// checksums, so both are tried.
+pub fn describe(buf: &[u8]) -> Option<(usize, String)> {
+    match buf.first() {
+	Some(&SOH) | Some(&STX) if buf.len() >= 3 && buf[1] as u32 + buf[2] as u32 == 255 => {
+	    let size = if buf[0] == STX { 1024 } else { 128 };
+	    let data = buf.get(3..3 + size)?;
+	    if buf.len() >= 3 + size + 2 {
+		let crc = crc_conn4x(init_crc_array(), data.to_vec());
+		if buf[3 + size] as u32 == crc >> 8 && buf[4 + size] as u32 == crc & 0xff {
+		    return Some((3 + size + 2, format!("XModem block {} ({} bytes), CRC {:04X} ok", buf[1], size, crc)));
 		}
-	)
-    );
-
-    return final_path; // used in main for crc calculation
+	    }
+	    let sum = data.iter().map(|b| *b as u32).sum::<u32>() as u8;
+	    let check = *buf.get(3 + size)?;
+	    return Some((3 + size + 1, match check == sum {
+		true => format!("XModem block {} ({} bytes), checksum {:02X} ok", buf[1], size, sum),
+		false => format!("XModem block {} ({} bytes), checksum {:02X} bad (expected {:02X} or a CRC)",
+				 buf[1], size, check, sum),
+	    }));
+	},
+	Some(&cmd) if cmd == b'P' || cmd == b'G' || cmd == b'E' => {
+	    let len = ((*buf.get(1)? as usize) << 8) | *buf.get(2)? as usize;
+	    let data = buf.get(3..3 + len)?;
+	    let check = *buf.get(3 + len)?;
+	    if data.iter().map(|b| *b as u32).sum::<u32>() as u8 != check {
+		return None;
+	    }
+	    let text: String = data.iter().map(|c| crate::helpers::hp_char_to_char(*c)).collect();
+	    return Some((len + 4, format!("XModem server command {} {:?}", cmd as char, text)));
+	},
+	_ => None,
+    }
 }
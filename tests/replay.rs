@@ -0,0 +1,43 @@
+// Play back the captures in tests/fixtures with --replay, so the
+// transfers are checked byte for byte without a calculator. To add
+// one, run the command with --trace against a calculator (or a fake
+// server) and put the trace and the files it sends in tests/fixtures.
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn replay(trace: &str, args: &[&str]) -> Output {
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures");
+    Command::new(env!("CARGO_BIN_EXE_alum"))
+	.current_dir(&fixtures)
+	// don't pick up the profile of whoever runs the tests
+	.env("ALUM_CONFIG", fixtures.join("no-config.toml"))
+	.arg("--replay").arg(trace)
+	.args(args)
+	.output()
+	.expect("couldn't run alum")
+}
+
+fn assert_matched(output: &Output) {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "replay failed:\n{}{}", stdout, stderr);
+    assert!(stdout.contains("matched"), "replay didn't finish:\n{}{}", stdout, stderr);
+}
+
+#[test]
+fn xmodem_send() {
+    assert_matched(&replay("xsend.trace", &["xsend", "prog.hp"]));
+}
+
+#[test]
+fn kermit_send() {
+    assert_matched(&replay("ksend.trace", &["ksend", "prog.hp"]));
+}
+
+#[test]
+fn wrong_protocol_differs() {
+    // Kermit packets where the capture has XModem ones
+    let output = replay("xsend.trace", &["ksend", "prog.hp"]);
+    assert!(!output.status.success());
+}